@@ -1,6 +1,6 @@
 use futures::{StreamExt, pin_mut};
 use ndgr_client::websocket::WebSocketClient;
-use ndgr_client::{fetch_program_info, stream_chunked_message};
+use ndgr_client::{StreamItem, fetch_program_info, stream_chunked_message};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -13,11 +13,15 @@ async fn main() -> anyhow::Result<()> {
     let view_uri = client.view_uri();
     println!("view_uri: {}", view_uri);
 
-    let stream = stream_chunked_message(view_uri).await;
+    let stream = stream_chunked_message(&view_uri).await;
     pin_mut!(stream);
 
     let mut count = 0;
-    while let Some(message) = stream.next().await {
+    while let Some(item) = stream.next().await {
+        let StreamItem::Message(message) = item? else {
+            println!("program ended");
+            break;
+        };
         println!("{:?}", message);
         count += 1;
         if count >= 5 {
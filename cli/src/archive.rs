@@ -0,0 +1,345 @@
+//! NDGR サーバーから受け取った生のバイト列を保存するアーカイブ形式。
+//!
+//! ファイルは [`MAGIC`] に続けて長さ付きの [`Record`] を並べたもので、全体を zstd で圧縮してもよい。
+//! [`Fetch`] が 1 回の HTTP リクエストを、[`Frame`] がその応答から切り出した 1 メッセージ分の
+//! バイト列を表す。ストリームの途中でセグメントを取りに行くので、`Frame` は `fetch_id` で
+//! どの `Fetch` に属するかを示す。
+//! 書き出しはファイルを扱うので wasm では使えない。
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::File;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::{self, BufReader, BufWriter, Read, Write};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicU32, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::Result;
+use prost::Message;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::sync::{mpsc, oneshot};
+
+/// 非圧縮のアーカイブの先頭 8 バイト
+pub const MAGIC: &[u8; 8] = b"NDGRARC\x01";
+
+/// zstd のフレームの先頭 4 バイト
+#[cfg(not(target_arch = "wasm32"))]
+const ZSTD_MAGIC: &[u8; 4] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// 読み込むレコードの上限。フレームの上限に、時刻などのフィールドの分の余裕を足したもの
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_RECORD_LEN: u64 = crate::codec::DEFAULT_MAX_FRAME_LEN as u64 + 1024;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Record {
+    /// 書き込んだ時刻 (UNIX ミリ秒)
+    #[prost(int64, tag = "1")]
+    pub received_at_ms: i64,
+    #[prost(oneof = "RecordKind", tags = "2, 3")]
+    pub kind: Option<RecordKind>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum RecordKind {
+    #[prost(message, tag = "2")]
+    Fetch(Fetch),
+    #[prost(message, tag = "3")]
+    Frame(Frame),
+}
+
+/// リクエストの開始
+#[derive(Clone, PartialEq, Message)]
+pub struct Fetch {
+    /// アーカイブ内で一意な番号
+    #[prost(uint32, tag = "1")]
+    pub id: u32,
+    #[prost(string, tag = "2")]
+    pub uri: String,
+    #[prost(enumeration = "PayloadKind", tag = "3")]
+    pub kind: i32,
+}
+
+/// 応答に含まれていた 1 メッセージ分のバイト列 (長さの前置きは含まない)
+#[derive(Clone, PartialEq, Message)]
+pub struct Frame {
+    #[prost(uint32, tag = "1")]
+    pub fetch_id: u32,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data: Vec<u8>,
+}
+
+/// [`Frame`] の中身の型
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum PayloadKind {
+    Unknown = 0,
+    ChunkedEntry = 1,
+    ChunkedMessage = 2,
+    PackedSegment = 3,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+/// アーカイブを書き出す。[`ArchiveWriter::finish`] の前に中断しても、
+/// 非圧縮なら書き込み済みの分は読める。
+pub struct ArchiveWriter {
+    sink: Sink,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+enum Sink {
+    Plain(Box<dyn Write + Send>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ArchiveWriter {
+    /// `path` にアーカイブを作る。`zstd_level` を渡すと zstd で圧縮する。
+    pub fn create(path: impl AsRef<Path>, zstd_level: Option<i32>) -> Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        let sink = match zstd_level {
+            Some(level) => Sink::Zstd(zstd::Encoder::new(file, level)?),
+            None => Sink::Plain(Box::new(file)),
+        };
+        Self::with_sink(sink)
+    }
+
+    /// 非圧縮で `out` に書き出す
+    pub fn new(out: impl Write + Send + 'static) -> Result<Self> {
+        Self::with_sink(Sink::Plain(Box::new(out)))
+    }
+
+    fn with_sink(sink: Sink) -> Result<Self> {
+        let mut writer = Self { sink };
+        writer.out().write_all(MAGIC)?;
+        Ok(writer)
+    }
+
+    fn out(&mut self) -> &mut dyn Write {
+        match &mut self.sink {
+            Sink::Plain(out) => out,
+            Sink::Zstd(encoder) => encoder,
+        }
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        self.out()
+            .write_all(&record.encode_length_delimited_to_vec())?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.out().flush()?;
+        Ok(())
+    }
+
+    /// zstd のフレームを閉じて書き出す
+    pub fn finish(self) -> Result<()> {
+        match self.sink {
+            Sink::Plain(mut out) => out.flush()?,
+            Sink::Zstd(encoder) => encoder.finish()?.flush()?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+/// アーカイブを先頭から読む。
+/// 記録が中断されてレコードの途中でファイルが終わっている場合は、そこまでを読んで終わる。
+pub struct ArchiveReader {
+    input: Box<dyn Read + Send>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ArchiveReader {
+    /// zstd で圧縮されているかどうかは中身を見て判断する
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut head = [0; 4];
+        file.read_exact(&mut head)?;
+        let input = io::Cursor::new(head).chain(file);
+        if &head == ZSTD_MAGIC {
+            Self::new(zstd::Decoder::new(input)?)
+        } else {
+            Self::new(input)
+        }
+    }
+
+    /// 非圧縮のアーカイブを `input` から読む
+    pub fn new(input: impl Read + Send + 'static) -> Result<Self> {
+        let mut input: Box<dyn Read + Send> = Box::new(input);
+        let mut magic = [0; MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            anyhow::bail!("not an ndgr archive");
+        }
+        Ok(Self { input })
+    }
+
+    /// 次のレコード。終わりに達したら `None`
+    pub fn read(&mut self) -> Result<Option<Record>> {
+        // 長さの varint を 1 バイトずつ読む
+        let mut len: u64 = 0;
+        for i in 0.. {
+            let mut byte = [0; 1];
+            match self.input.read_exact(&mut byte) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+            if i >= 10 {
+                anyhow::bail!("invalid record length");
+            }
+            len |= u64::from(byte[0] & 0x7f) << (7 * i);
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+
+        // 壊れたファイルの長さをそのまま信じて巨大な領域を確保しないようにする
+        if len > MAX_RECORD_LEN {
+            anyhow::bail!("record too large ({len} bytes)");
+        }
+        let mut data = vec![0; len as usize];
+        match self.input.read_exact(&mut data) {
+            Ok(()) => Ok(Some(Record::decode(data.as_slice())?)),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Iterator for ArchiveReader {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+/// [`NdgrClient::with_recorder`](crate::client::NdgrClient::with_recorder) で取得した
+/// バイト列をアーカイブに書き込む。clone したものは同じアーカイブに書き込む。
+/// ファイルへの書き込みと zstd の圧縮はブロッキングなので、専用のスレッドに渡して行う。
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+pub struct Recorder {
+    commands: mpsc::UnboundedSender<Command>,
+    next_fetch_id: Arc<AtomicU32>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+enum Command {
+    Write(Record),
+    Finish(oneshot::Sender<Result<()>>),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder").finish_non_exhaustive()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Recorder {
+    /// 書き込み用のスレッドを立てる。tokio のランタイムの中で呼ぶ。
+    pub fn new(writer: ArchiveWriter) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        tokio::task::spawn_blocking(move || write_records(writer, receiver));
+        Self {
+            commands,
+            next_fetch_id: Arc::new(AtomicU32::new(1)),
+        }
+    }
+
+    /// リクエストの開始を書き込み、以降の [`Recorder::frame`] に渡す番号を返す
+    pub(crate) fn begin_fetch(&self, uri: &str, kind: PayloadKind) -> u32 {
+        let id = self.next_fetch_id.fetch_add(1, Ordering::Relaxed);
+        self.write(RecordKind::Fetch(Fetch {
+            id,
+            uri: uri.to_string(),
+            kind: kind as i32,
+        }));
+        id
+    }
+
+    pub(crate) fn frame(&self, fetch_id: u32, data: &[u8]) {
+        self.write(RecordKind::Frame(Frame {
+            fetch_id,
+            data: data.to_vec(),
+        }));
+    }
+
+    fn write(&self, kind: RecordKind) {
+        let record = Record {
+            received_at_ms: now_ms(),
+            kind: Some(kind),
+        };
+        // 書き込みを終えた後の記録は捨てる
+        let _ = self.commands.send(Command::Write(record));
+    }
+
+    /// 溜まっている記録を書き終えてファイルを閉じる。途中で書き込みに失敗していれば
+    /// そのエラーを返す。以降の記録は捨てられる。
+    pub async fn finish(&self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        if self.commands.send(Command::Finish(tx)).is_err() {
+            // すでに閉じている
+            return Ok(());
+        }
+        rx.await
+            .map_err(|_| anyhow::anyhow!("archive writer stopped unexpectedly"))?
+    }
+}
+
+/// 書き込み用のスレッドの本体。最初に起きた書き込みエラーは、取得の邪魔をしないよう
+/// その場では返さずに [`Recorder::finish`] で返す。
+#[cfg(not(target_arch = "wasm32"))]
+fn write_records(mut writer: ArchiveWriter, mut commands: mpsc::UnboundedReceiver<Command>) {
+    let mut error = None;
+    while let Some(command) = commands.blocking_recv() {
+        match command {
+            Command::Write(record) => {
+                if error.is_none()
+                    && let Err(e) = writer.write(&record)
+                {
+                    error = Some(e);
+                }
+            }
+            Command::Finish(reply) => {
+                let result = match error {
+                    Some(e) => Err(e),
+                    None => writer.finish(),
+                };
+                // 以降の記録は受け付けず、同時に呼ばれた finish には閉じたことだけを返す
+                commands.close();
+                let _ = reply.send(result);
+                while let Ok(command) = commands.try_recv() {
+                    if let Command::Finish(reply) = command {
+                        let _ = reply.send(Ok(()));
+                    }
+                }
+                return;
+            }
+        }
+    }
+    // どの Recorder も finish せずに捨てられたら、書き込めた分だけでも閉じておく
+    if error.is_none() {
+        let _ = writer.finish();
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as i64)
+}
@@ -0,0 +1,481 @@
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_stream::stream;
+use bytes::{Bytes, BytesMut};
+use futures_core::stream::Stream;
+use futures_util::StreamExt;
+use protobuf::chat::service::edge::{ChunkedEntry, ChunkedMessage};
+use reqwest::header::{COOKIE, HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+
+use crate::ViewQuery;
+use crate::archive::PayloadKind;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::archive::Recorder;
+use crate::codec::{DecodeError, LengthDelimitedProtobufDecoder};
+use crate::error::{ClientBuildError, ProgramInfoError};
+use crate::program_info::ProgramInfo;
+
+/// 接続まわりの設定。[`NdgrClientBuilder::config`] で渡す。
+/// wasm ではブラウザの fetch に任せるので、タイムアウトは `request_timeout` だけが効き、
+/// プロキシは使えない。
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    pub user_agent: String,
+    /// 接続の確立までのタイムアウト
+    pub connect_timeout: Option<Duration>,
+    /// 番組ページや過去ログのセグメントなど、一度で受け取り切るリクエスト全体のタイムアウト。
+    /// 長く続く NDGR のストリームには掛けない。
+    pub request_timeout: Option<Duration>,
+    /// 受信が途切れてからのタイムアウト。ストリームにも掛かる。
+    pub read_timeout: Option<Duration>,
+    /// `http://` / `https://` / `socks5://` / `socks5h://` のプロキシ URL。
+    /// WebSocket は `http://` と `socks5://` / `socks5h://` のみ対応。
+    pub proxy: Option<String>,
+    /// すべてのリクエストと WebSocket のハンドシェイクに付けるヘッダー
+    pub headers: Vec<(String, String)>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: concat!("ndgr-client/", env!("CARGO_PKG_VERSION")).to_string(),
+            connect_timeout: Some(Duration::from_secs(10)),
+            request_timeout: Some(Duration::from_secs(30)),
+            read_timeout: None,
+            proxy: None,
+            headers: Vec::new(),
+        }
+    }
+}
+
+/// 番組ページ・WebSocket・NDGR サーバーへのリクエストで共有する HTTP クライアント。
+/// ログイン中のセッション Cookie を持たせると、会員限定の番組の視聴や自分としての投稿ができる。
+/// 内部のコネクションプールを共有するので、使い回すときは `clone` する。
+#[derive(Debug, Clone)]
+pub struct NdgrClient {
+    http: Result<reqwest::Client, ClientBuildError>,
+    /// WebSocket のハンドシェイクにも付けるヘッダー
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    headers: HeaderMap,
+    /// `*.nicovideo.jp` 宛てのリクエストだけに付ける `Cookie` ヘッダー
+    cookie: Option<HeaderValue>,
+    request_timeout: Option<Duration>,
+    /// WebSocket をプロキシ経由で張るときにも掛ける
+    #[cfg(not(target_arch = "wasm32"))]
+    connect_timeout: Option<Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    proxy: Option<reqwest::Url>,
+    #[cfg(not(target_arch = "wasm32"))]
+    recorder: Option<Recorder>,
+}
+
+/// [`NdgrClient::default`] が返すクライアント。呼ぶたびに作るとコネクションを使い回せない
+static DEFAULT_CLIENT: LazyLock<NdgrClient> = LazyLock::new(|| {
+    NdgrClient::builder()
+        .build()
+        .unwrap_or_else(|e| NdgrClient {
+            http: Err(ClientBuildError(Arc::new(e))),
+            headers: HeaderMap::new(),
+            cookie: None,
+            request_timeout: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            connect_timeout: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            proxy: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            recorder: None,
+        })
+});
+
+impl Default for NdgrClient {
+    /// 既定の [`ClientConfig`] で Cookie なし。プロセス全体で 1 つのクライアントを共有する。
+    /// 組み立てに失敗していたら、panic せずにリクエストのたびに [`ClientBuildError`] を返す。
+    fn default() -> Self {
+        DEFAULT_CLIENT.clone()
+    }
+}
+
+impl NdgrClient {
+    pub fn builder() -> NdgrClientBuilder {
+        NdgrClientBuilder::default()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// `url` が `*.nicovideo.jp` 宛てなら付ける `Cookie` ヘッダー。
+    /// CORS プロキシなどほかのホストにログインセッションを渡さない。
+    pub(crate) fn cookie_for(&self, url: &str) -> Option<&HeaderValue> {
+        let cookie = self.cookie.as_ref()?;
+        let url = reqwest::Url::parse(url).ok()?;
+        is_nicovideo_host(url.host_str()?).then_some(cookie)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn proxy(&self) -> Option<&reqwest::Url> {
+        self.proxy.as_ref()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout
+    }
+
+    /// 一度で受け取り切るリクエスト。`request_timeout` を掛ける。
+    fn get(&self, url: &str) -> Result<reqwest::RequestBuilder, ClientBuildError> {
+        let request = self.request(url)?;
+        Ok(match self.request_timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        })
+    }
+
+    fn request(&self, url: &str) -> Result<reqwest::RequestBuilder, ClientBuildError> {
+        let request = self.http.as_ref().map_err(Clone::clone)?.get(url);
+        Ok(match self.cookie_for(url) {
+            Some(cookie) => request.header(COOKIE, cookie.clone()),
+            None => request,
+        })
+    }
+
+    /// 番組ページから番組情報を取得する。
+    /// 視聴できない番組 (終了済み・未開始・要ログインなど) はその理由をエラーで返す。
+    /// タイムシフトが視聴できる終了済みの番組は `Ok` になる。
+    pub async fn fetch_program_info(&self, url: &str) -> Result<ProgramInfo, ProgramInfoError> {
+        let info = self.fetch_embedded_data(url).await?;
+        crate::check_watchable(&info)?;
+        Ok(info)
+    }
+
+    /// 番組ページの `#embedded-data` を読むだけで、視聴できるかどうかは確かめない
+    pub(crate) async fn fetch_embedded_data(
+        &self,
+        url: &str,
+    ) -> Result<ProgramInfo, ProgramInfoError> {
+        let response = self.get(url)?.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ProgramInfoError::HttpStatus(status));
+        }
+        let html = response.text().await?;
+
+        let document = scraper::Html::parse_document(&html);
+        let selector = scraper::Selector::parse("#embedded-data").unwrap();
+
+        let data_props = document
+            .select(&selector)
+            .next()
+            .and_then(|element| element.value().attr("data-props"))
+            .ok_or(ProgramInfoError::EmbeddedDataNotFound)?;
+        Ok(serde_json::from_str(data_props)?)
+    }
+
+    pub async fn fetch_chunked_entry(
+        &self,
+        url: &str,
+        query: &ViewQuery,
+    ) -> impl Stream<Item = Result<ChunkedEntry>> + use<> {
+        let at_str = match query {
+            ViewQuery::Now => "now".to_string(),
+            ViewQuery::At(at) => at.to_string(),
+        };
+        let url = format!("{url}?at={at_str}");
+
+        self.fetch_protobuf_stream_as::<ChunkedEntry>(&url, PayloadKind::ChunkedEntry)
+            .await
+    }
+
+    pub async fn fetch_chunked_message(
+        &self,
+        url: &str,
+    ) -> impl Stream<Item = Result<ChunkedMessage>> + use<> {
+        self.fetch_protobuf_stream_as::<ChunkedMessage>(url, PayloadKind::ChunkedMessage)
+            .await
+    }
+
+    pub async fn fetch_protobuf_stream<T: prost::Message + Default>(
+        &self,
+        url: &str,
+    ) -> impl Stream<Item = Result<T>> + use<T> {
+        self.fetch_protobuf_stream_as::<T>(url, PayloadKind::Unknown)
+            .await
+    }
+
+    /// `kind` は記録するときにアーカイブに残す中身の型。
+    /// 中身が読めないフレームは `Err` を流して次のフレームに進み、
+    /// 区切りが分からなくなった場合や通信エラー、エラーのステータスは `Err` を流して終わる。
+    async fn fetch_protobuf_stream_as<T: prost::Message + Default>(
+        &self,
+        url: &str,
+        kind: PayloadKind,
+    ) -> impl Stream<Item = Result<T>> + use<T> {
+        let recording = self.begin_recording(url, kind);
+        let response = match self.request(url) {
+            Ok(request) => request.send().await.map_err(Into::into),
+            Err(e) => Err(anyhow::Error::from(e)),
+        };
+
+        stream! {
+            // エラーのステータスを空のストリームとして読むと番組終了と見分けが付かない
+            let response = response.and_then(|response| Ok(response.error_for_status()?));
+            let mut stream = match response {
+                Ok(response) => response.bytes_stream(),
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            };
+            let mut decoder = LengthDelimitedProtobufDecoder::<T>::new();
+            let mut buffer = BytesMut::new();
+
+            loop {
+                let eof = match stream.next().await {
+                    Some(Ok(chunk)) => {
+                        buffer.extend_from_slice(&chunk);
+                        false
+                    }
+                    Some(Err(e)) => {
+                        yield Err(e.into());
+                        return;
+                    }
+                    None => true,
+                };
+
+                loop {
+                    let frame = if eof {
+                        decoder.decode_frame_eof(&mut buffer)
+                    } else {
+                        decoder.decode_frame(&mut buffer)
+                    };
+                    match frame {
+                        Ok(Some(frame)) => {
+                            recording.frame(&frame);
+                            yield T::decode(frame).map_err(|e| DecodeError::from(e).into());
+                        }
+                        Ok(None) => break,
+                        Err(e) => {
+                            yield Err(e.into());
+                            return;
+                        }
+                    }
+                }
+                if eof {
+                    return;
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn fetch_bytes(&self, url: &str, kind: PayloadKind) -> Result<Bytes> {
+        let recording = self.begin_recording(url, kind);
+        let bytes = self
+            .get(url)?
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        recording.frame(&bytes);
+        Ok(bytes)
+    }
+
+    /// 取得したバイト列を `recorder` のアーカイブにも書き込むクライアントを返す
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_recorder(&self, recorder: Recorder) -> Self {
+        Self {
+            recorder: Some(recorder),
+            ..self.clone()
+        }
+    }
+
+    fn begin_recording(&self, url: &str, kind: PayloadKind) -> FrameRecording {
+        #[cfg(not(target_arch = "wasm32"))]
+        return FrameRecording {
+            target: self
+                .recorder
+                .as_ref()
+                .map(|recorder| (recorder.clone(), recorder.begin_fetch(url, kind))),
+        };
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = (url, kind);
+            FrameRecording {}
+        }
+    }
+}
+
+/// 1 回のリクエストで受け取ったフレームの記録先。記録しないときや wasm では何もしない。
+struct FrameRecording {
+    #[cfg(not(target_arch = "wasm32"))]
+    target: Option<(Recorder, u32)>,
+}
+
+impl FrameRecording {
+    fn frame(&self, data: &[u8]) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some((recorder, fetch_id)) = &self.target {
+            recorder.frame(*fetch_id, data);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = data;
+    }
+}
+
+/// [`NdgrClient`] を組み立てる
+#[derive(Debug, Clone, Default)]
+pub struct NdgrClientBuilder {
+    cookies: Vec<(String, String)>,
+    config: ClientConfig,
+}
+
+impl NdgrClientBuilder {
+    pub fn config(mut self, config: ClientConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// ログインセッションの `user_session` Cookie の値を設定する
+    pub fn user_session(self, value: impl Into<String>) -> Self {
+        self.cookie("user_session", value)
+    }
+
+    /// 任意の Cookie を追加する。同じ名前の Cookie は上書きする。
+    pub fn cookie(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+        self.cookies.retain(|(existing, _)| *existing != name);
+        self.cookies.push((name, value.into()));
+        self
+    }
+
+    /// ファイルから Cookie を読み込む。
+    /// Netscape 形式の Cookie ファイル (`cookies.txt`) なら nicovideo.jp の Cookie をすべて、
+    /// そうでなければファイルの中身を `user_session` の値 (`user_session=` は省略可) として使う。
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn cookie_file(self, path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read cookie file: {}", path.display()))?;
+        self.cookie_text(&content)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn cookie_text(mut self, content: &str) -> Result<Self> {
+        let jar = parse_netscape_cookies(content);
+        if !jar.is_empty() {
+            for (name, value) in jar {
+                self = self.cookie(name, value);
+            }
+            return Ok(self);
+        }
+
+        let value = content.trim();
+        let value = value.strip_prefix("user_session=").unwrap_or(value);
+        if value.is_empty() || value.contains(char::is_whitespace) {
+            anyhow::bail!("cookie file contains neither a cookie jar nor a user_session value");
+        }
+        Ok(self.user_session(value))
+    }
+
+    pub fn build(self) -> Result<NdgrClient> {
+        let config = self.config;
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("invalid header name: {name}"))?;
+            let value = HeaderValue::from_str(value)
+                .with_context(|| format!("invalid value for header {name}"))?;
+            headers.append(name, value);
+        }
+        let user_agent = HeaderValue::from_str(&config.user_agent).context("invalid user agent")?;
+        headers.insert(USER_AGENT, user_agent);
+        let cookie = if self.cookies.is_empty() {
+            None
+        } else {
+            let cookie = self
+                .cookies
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>()
+                .join("; ");
+            let mut value = HeaderValue::from_str(&cookie).context("invalid cookie value")?;
+            value.set_sensitive(true);
+            Some(value)
+        };
+
+        let builder = reqwest::Client::builder().default_headers(headers.clone());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let (builder, proxy) = {
+            let mut builder = builder;
+            if let Some(timeout) = config.connect_timeout {
+                builder = builder.connect_timeout(timeout);
+            }
+            if let Some(timeout) = config.read_timeout {
+                builder = builder.read_timeout(timeout);
+            }
+            let proxy = match &config.proxy {
+                Some(proxy) => {
+                    builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+                    Some(reqwest::Url::parse(proxy).context("invalid proxy url")?)
+                }
+                None => None,
+            };
+            (builder, proxy)
+        };
+
+        Ok(NdgrClient {
+            http: Ok(builder.build()?),
+            headers,
+            cookie,
+            request_timeout: config.request_timeout,
+            #[cfg(not(target_arch = "wasm32"))]
+            connect_timeout: config.connect_timeout,
+            #[cfg(not(target_arch = "wasm32"))]
+            proxy,
+            #[cfg(not(target_arch = "wasm32"))]
+            recorder: None,
+        })
+    }
+}
+
+fn is_nicovideo_host(host: &str) -> bool {
+    host == "nicovideo.jp" || host.ends_with(".nicovideo.jp")
+}
+
+/// Netscape 形式の Cookie ファイルから nicovideo.jp 向けの Cookie を取り出す。
+/// 期限切れの Cookie は読み飛ばす。
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_netscape_cookies(content: &str) -> Vec<(String, String)> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+
+    content
+        .lines()
+        .filter_map(|line| {
+            // curl などは HttpOnly の Cookie の行頭にこの印を付ける
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.starts_with('#') {
+                return None;
+            }
+            let fields: Vec<&str> = line.trim_end_matches(['\r', '\n']).split('\t').collect();
+            let [domain, _, _, _, expires, name, value] = fields[..] else {
+                return None;
+            };
+            let domain = domain.trim_start_matches('.');
+            if domain != "nicovideo.jp" && !domain.ends_with(".nicovideo.jp") {
+                return None;
+            }
+            let expires: i64 = expires.parse().ok()?;
+            if expires != 0 && expires < now {
+                return None;
+            }
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
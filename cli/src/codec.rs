@@ -0,0 +1,149 @@
+//! NDGR の HTTP ストリームで使われる、varint の長さを前置きした protobuf メッセージの切り出し
+
+use std::io;
+use std::marker::PhantomData;
+
+use bytes::{Buf, Bytes, BytesMut};
+use tokio_util::codec::Decoder;
+
+/// [`LengthDelimitedProtobufDecoder::new`] で使うフレームの上限
+pub const DEFAULT_MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// 長さの varint の最大バイト数
+const MAX_DELIMITER_LEN: usize = 10;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    /// 長さの varint が壊れている。以降の区切りが分からないので続きは読めない。
+    #[error("invalid length delimiter")]
+    InvalidLength,
+    /// 長さが上限を超えている。以降の区切りが分からないので続きは読めない。
+    #[error("frame of {len} bytes exceeds the limit of {max} bytes")]
+    FrameTooLarge { len: u64, max: usize },
+    /// フレームは切り出せたが中身が `T` として読めない。フレームは消費済みなので、
+    /// [`Decoder::decode`] を直接呼んでいれば次のフレームから続けて読める。
+    #[error("failed to decode frame: {0}")]
+    Message(#[from] prost::DecodeError),
+    /// フレームの途中でストリームが終わった
+    #[error("stream ended in the middle of a frame ({0} bytes left)")]
+    Truncated(usize),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl DecodeError {
+    /// このエラーの後も同じバッファから読み進められるかどうか。
+    /// `FramedRead` は `Err` を返した時点でストリームを終えるので、続けて読むには
+    /// `NdgrClient::fetch_protobuf_stream_as` のように自前のループでフレームを切り出して
+    /// デコードする。
+    pub fn is_recoverable(&self) -> bool {
+        matches!(self, DecodeError::Message(_))
+    }
+}
+
+/// 長さ付きの protobuf メッセージ `T` を 1 つずつ取り出す [`Decoder`]。
+/// 届いた分が足りなければ `Ok(None)` を返して続きを待ち、壊れたデータは `Err` にする。
+/// フレームは受信バッファから切り出した [`Bytes`] のままデコードするのでコピーしない。
+#[derive(Debug)]
+pub struct LengthDelimitedProtobufDecoder<T> {
+    max_frame_len: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for LengthDelimitedProtobufDecoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for LengthDelimitedProtobufDecoder<T> {
+    fn clone(&self) -> Self {
+        Self::with_max_frame_len(self.max_frame_len)
+    }
+}
+
+impl<T> LengthDelimitedProtobufDecoder<T> {
+    /// フレームの上限は [`DEFAULT_MAX_FRAME_LEN`]
+    pub fn new() -> Self {
+        Self::with_max_frame_len(DEFAULT_MAX_FRAME_LEN)
+    }
+
+    pub fn with_max_frame_len(max_frame_len: usize) -> Self {
+        Self {
+            max_frame_len,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn max_frame_len(&self) -> usize {
+        self.max_frame_len
+    }
+
+    /// `src` の先頭からフレームを 1 つ切り出す (長さの前置きは含まない)。
+    /// 長さが分かった時点で上限を確かめるので、大きすぎるフレームを溜め込むことはない。
+    pub fn decode_frame(&mut self, src: &mut BytesMut) -> Result<Option<Bytes>, DecodeError> {
+        let Some((len, delimiter_len)) = self.peek_length(src)? else {
+            return Ok(None);
+        };
+        if src.len() < delimiter_len + len {
+            src.reserve(delimiter_len + len - src.len());
+            return Ok(None);
+        }
+        src.advance(delimiter_len);
+        Ok(Some(src.split_to(len).freeze()))
+    }
+
+    /// ストリームの終わりで呼ぶ [`Self::decode_frame`]。
+    /// 途中までしか届いていないフレームが残っていれば [`DecodeError::Truncated`] にする。
+    pub fn decode_frame_eof(&mut self, src: &mut BytesMut) -> Result<Option<Bytes>, DecodeError> {
+        match self.decode_frame(src)? {
+            Some(frame) => Ok(Some(frame)),
+            None if src.is_empty() => Ok(None),
+            None => {
+                let len = src.len();
+                src.clear();
+                Err(DecodeError::Truncated(len))
+            }
+        }
+    }
+
+    /// 長さとその varint のバイト数。varint が届ききっていなければ `None`
+    fn peek_length(&self, src: &[u8]) -> Result<Option<(usize, usize)>, DecodeError> {
+        let mut len: u64 = 0;
+        for (i, byte) in src.iter().take(MAX_DELIMITER_LEN).enumerate() {
+            len |= u64::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                return match usize::try_from(len) {
+                    Ok(frame_len) if frame_len <= self.max_frame_len => {
+                        Ok(Some((frame_len, i + 1)))
+                    }
+                    _ => Err(DecodeError::FrameTooLarge {
+                        len,
+                        max: self.max_frame_len,
+                    }),
+                };
+            }
+        }
+        if src.len() >= MAX_DELIMITER_LEN {
+            return Err(DecodeError::InvalidLength);
+        }
+        Ok(None)
+    }
+}
+
+impl<T: prost::Message + Default> Decoder for LengthDelimitedProtobufDecoder<T> {
+    type Item = T;
+    type Error = DecodeError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, DecodeError> {
+        self.decode_frame(src)?
+            .map(|frame| T::decode(frame).map_err(Into::into))
+            .transpose()
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<T>, DecodeError> {
+        self.decode_frame_eof(src)?
+            .map(|frame| T::decode(frame).map_err(Into::into))
+            .transpose()
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use futures::StreamExt;
+use futures::stream::LocalBoxStream;
+use ndgr_client::client::NdgrClient;
+use ndgr_client::program_info::ProgramInfo;
+use ndgr_client::resume::StreamResume;
+use ndgr_client::retry::StreamError;
+use ndgr_client::timeshift::{TimeshiftOptions, stream_timeshift};
+use ndgr_client::{StreamItem, StreamOptions, stream_chunked_message_with_options};
+
+pub mod dump;
+pub mod info;
+pub mod multi;
+pub mod record;
+pub mod replay;
+pub mod tui;
+pub mod watch;
+
+/// dump / replay / info の出力形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// 人が読むための形式
+    #[default]
+    Text,
+    /// 1 行に 1 つの JSON
+    Json,
+    /// タブ区切り (dump / replay のみ)
+    Tsv,
+}
+
+/// `--speed` の値。0 より大きい数だけを受け付ける
+pub fn parse_speed(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(speed) if speed > 0.0 => Ok(speed),
+        Ok(_) => Err("0 より大きい数を指定してください".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ProgramArgs {
+    /// 視聴ページの URL、番組 ID (lv...)、放送中のコミュニティ・チャンネル ID (co... / ch...)
+    pub program: String,
+}
+
+impl ProgramArgs {
+    pub async fn fetch_info(&self, client: &NdgrClient) -> Result<ProgramInfo> {
+        let url = client.resolve_watch_url(&self.program).await?;
+        Ok(client.fetch_program_info(&url).await?)
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct TimeshiftArgs {
+    /// タイムシフトで再生する
+    #[arg(long)]
+    pub timeshift: bool,
+    /// タイムシフトの再生開始位置 (番組開始からの秒数)
+    #[arg(long, default_value_t = 0, requires = "timeshift")]
+    pub from: i64,
+    /// タイムシフトの再生速度
+    #[arg(long, default_value_t = 1.0, requires = "timeshift", value_parser = parse_speed)]
+    pub speed: f64,
+}
+
+impl TimeshiftArgs {
+    /// `--timeshift` が指定されていなければ `None`
+    pub fn options(&self, info: &ProgramInfo, client: &NdgrClient) -> Option<TimeshiftOptions> {
+        self.timeshift.then(|| {
+            let mut options = TimeshiftOptions {
+                speed: self.speed,
+                ..TimeshiftOptions::new(info.program.begin_time + self.from)
+            };
+            options.stream.client = client.clone();
+            options
+        })
+    }
+}
+
+pub type MessageStream = LocalBoxStream<'static, Result<StreamItem, StreamError>>;
+
+/// タイムシフトなら再生位置から、そうでなければ最新からメッセージを流す。
+/// `resume` に受け取った記録があれば、その続きから流す。
+pub async fn open_stream(
+    client: &NdgrClient,
+    view_uri: &str,
+    timeshift: Option<&TimeshiftOptions>,
+    resume: &StreamResume,
+) -> MessageStream {
+    match timeshift {
+        Some(options) => stream_timeshift(view_uri, resume.resume_timeshift(options.clone()))
+            .await
+            .boxed_local(),
+        None => {
+            let options = StreamOptions {
+                client: client.clone(),
+                ..StreamOptions::default()
+            };
+            stream_chunked_message_with_options(view_uri, resume.resume(options))
+                .await
+                .boxed_local()
+        }
+    }
+}
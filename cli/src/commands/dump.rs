@@ -0,0 +1,238 @@
+use std::io::{self, Write, stdout};
+
+use anyhow::Result;
+use clap::Args;
+use futures::StreamExt;
+use ndgr_client::StreamItem;
+use ndgr_client::client::NdgrClient;
+use ndgr_client::model::Message;
+use ndgr_client::resume::StreamResume;
+use ndgr_client::timeshift::TimeshiftOptions;
+use ndgr_client::websocket::WebSocketClient;
+use protobuf::chat::service::edge::ChunkedMessage;
+use serde_json::Value;
+use tokio::select;
+use tokio::sync::watch;
+
+use crate::commands::{OutputFormat, ProgramArgs, TimeshiftArgs, open_stream};
+
+/// TSV で `--fields` を省略したときの項目
+const DEFAULT_TSV_FIELDS: &[&str] = &[
+    "at",
+    "type",
+    "no",
+    "rawUserId",
+    "hashedUserId",
+    "name",
+    "content",
+];
+
+#[derive(Debug, Args)]
+pub struct DumpArgs {
+    #[command(flatten)]
+    program: ProgramArgs,
+    #[command(flatten)]
+    timeshift: TimeshiftArgs,
+    /// 接続より前のコメントをこの件数まで先に出力する (タイムシフトでは無視)
+    #[arg(long, default_value_t = 0)]
+    history: usize,
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+/// dump / replay の出力の設定
+#[derive(Debug, Args)]
+pub struct OutputArgs {
+    /// 出力する項目をカンマ区切りの JSON のキー名で指定する
+    /// (例: at,type,no,content)。省略すると JSON はすべて、TSV は
+    /// at,type,no,rawUserId,hashedUserId,name,content
+    #[arg(long, value_delimiter = ',', value_parser = parse_field)]
+    fields: Vec<String>,
+    /// チャット・ギフト・ニコニ広告・通知だけを出力する
+    #[arg(long)]
+    comments_only: bool,
+    /// TSV の見出し行を出力しない
+    #[arg(long)]
+    no_header: bool,
+}
+
+pub async fn run(client: &NdgrClient, args: DumpArgs, format: OutputFormat) -> Result<()> {
+    let info = args.program.fetch_info(client).await?;
+    let timeshift = args.timeshift.options(&info, client);
+
+    let web_socket_client =
+        WebSocketClient::connect(client, &info.site.relive.web_socket_url).await?;
+    let view_uri_rx = web_socket_client.subscribe_view_uri();
+
+    let mut writer = args.output.writer(stdout().lock(), format);
+
+    let result = dump(client, &args, &mut writer, view_uri_rx, timeshift).await;
+
+    web_socket_client.shutdown().await;
+
+    ignore_broken_pipe(result)
+}
+
+/// `| head` などで読み手が先に終わったのは正常終了として扱う
+pub fn ignore_broken_pipe(result: Result<()>) -> Result<()> {
+    match result {
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+async fn dump(
+    client: &NdgrClient,
+    args: &DumpArgs,
+    writer: &mut MessageWriter<impl Write>,
+    mut view_uri_rx: watch::Receiver<String>,
+    timeshift: Option<TimeshiftOptions>,
+) -> Result<()> {
+    let view_uri = view_uri_rx.borrow_and_update().clone();
+
+    writer.write_header()?;
+
+    let mut resume = StreamResume::new();
+    if args.history > 0 && timeshift.is_none() {
+        let history = client.fetch_history(&view_uri, args.history).await?;
+        for message in &history.messages {
+            writer.write(message)?;
+        }
+        resume.extend_seen(history.message_ids());
+    }
+
+    let mut stream = open_stream(client, &view_uri, timeshift.as_ref(), &resume).await;
+
+    loop {
+        select! {
+            item = stream.next() => {
+                match item {
+                    Some(Ok(StreamItem::Message(message))) => {
+                        resume.record(&message);
+                        writer.write(&message)?;
+                    }
+                    Some(Ok(StreamItem::ProgramEnded)) | None => return Ok(()),
+                    Some(Err(e)) => eprintln!("{e}"),
+                }
+            }
+            Ok(()) = view_uri_rx.changed() => {
+                // 再接続でメッセージサーバーが変わったら、続きからストリームを張り直す
+                let view_uri = view_uri_rx.borrow_and_update().clone();
+                stream = open_stream(client, &view_uri, timeshift.as_ref(), &resume).await;
+            }
+        }
+    }
+}
+
+impl OutputArgs {
+    pub fn writer<W: Write>(&self, out: W, format: OutputFormat) -> MessageWriter<W> {
+        let fields = if self.fields.is_empty() && format == OutputFormat::Tsv {
+            DEFAULT_TSV_FIELDS.iter().map(|s| s.to_string()).collect()
+        } else {
+            self.fields.clone()
+        };
+        MessageWriter {
+            out,
+            format,
+            fields,
+            comments_only: self.comments_only,
+            header: format == OutputFormat::Tsv && !self.no_header,
+        }
+    }
+}
+
+/// [`Message`] の JSON 表現 (wasm 版と同じ形) を 1 件 1 行で書き出す
+pub struct MessageWriter<W> {
+    out: W,
+    format: OutputFormat,
+    /// 空ならすべての項目 (JSON のみ)
+    fields: Vec<String>,
+    comments_only: bool,
+    /// TSV の見出し行を書くかどうか
+    header: bool,
+}
+
+impl<W: Write> MessageWriter<W> {
+    /// 必要なら最初に見出し行を書く
+    pub fn write_header(&mut self) -> Result<()> {
+        if self.header {
+            writeln!(self.out, "{}", self.fields.join("\t"))?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, message: &ChunkedMessage) -> Result<()> {
+        let Some(message) = Message::from_chunked_message(message) else {
+            return Ok(());
+        };
+        if self.comments_only && !message.event.is_comment() {
+            return Ok(());
+        }
+
+        match self.format {
+            OutputFormat::Text => writeln!(self.out, "{message}")?,
+            OutputFormat::Json => {
+                let mut value = serde_json::to_value(&message)?;
+                if !self.fields.is_empty() {
+                    value = Value::Object(
+                        self.fields
+                            .iter()
+                            .map(|field| (field.clone(), value[field.as_str()].clone()))
+                            .collect(),
+                    );
+                }
+                writeln!(self.out, "{value}")?;
+            }
+            OutputFormat::Tsv => {
+                let value = serde_json::to_value(&message)?;
+                let columns: Vec<String> = self
+                    .fields
+                    .iter()
+                    .map(|field| tsv_column(&value[field.as_str()]))
+                    .collect();
+                writeln!(self.out, "{}", columns.join("\t"))?;
+            }
+        }
+        // パイプの先にすぐ届くように 1 件ずつ書き出す
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// `--fields` の項目名。[`Message`] の JSON のキーだけを受け付ける
+fn parse_field(value: &str) -> Result<String, String> {
+    if Message::FIELDS.contains(&value) {
+        Ok(value.to_string())
+    } else {
+        Err(format!(
+            "不明な項目です (指定できる項目: {})",
+            Message::FIELDS.join(",")
+        ))
+    }
+}
+
+/// 文字列はそのまま、`null` は空欄、それ以外は JSON で書く。
+/// タブ・改行・バックスラッシュはエスケープする。
+fn tsv_column(value: &Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    };
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
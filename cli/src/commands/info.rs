@@ -0,0 +1,86 @@
+use anyhow::Result;
+use clap::Args;
+use ndgr_client::client::NdgrClient;
+use ndgr_client::program_info::{ProgramInfo, ProgramStatus, ProviderType};
+
+use crate::commands::{OutputFormat, ProgramArgs};
+
+#[derive(Debug, Args)]
+pub struct InfoArgs {
+    #[command(flatten)]
+    program: ProgramArgs,
+}
+
+pub async fn run(client: &NdgrClient, args: InfoArgs, format: OutputFormat) -> Result<()> {
+    let info = args.program.fetch_info(client).await?;
+
+    match format {
+        OutputFormat::Text => print_text(&info),
+        OutputFormat::Json => println!("{}", serde_json::to_string(&info)?),
+        OutputFormat::Tsv => anyhow::bail!("info does not support --format tsv"),
+    }
+    Ok(())
+}
+
+fn print_text(info: &ProgramInfo) {
+    let program = &info.program;
+
+    println!("{} {}", program.nicolive_program_id, program.title);
+    println!("URL: {}", program.watch_page_url);
+    println!("状態: {}", status_label(program.status));
+    println!(
+        "提供: {} ({})",
+        program.supplier.name,
+        provider_label(program.provider_type)
+    );
+    if !info.social_group.id.is_empty() {
+        println!("{}: {}", info.social_group.id, info.social_group.name);
+    }
+    let end_time = if program.end_time > 0 {
+        program.end_time
+    } else {
+        program.scheduled_end_time
+    };
+    if program.begin_time > 0 && end_time > program.begin_time {
+        println!(
+            "放送時間: {}",
+            format_duration(end_time - program.begin_time)
+        );
+    }
+    if !program.tag.list.is_empty() {
+        let tags: Vec<&str> = program
+            .tag
+            .list
+            .iter()
+            .map(|tag| tag.text.as_str())
+            .collect();
+        println!("タグ: {}", tags.join(", "));
+    }
+}
+
+fn status_label(status: ProgramStatus) -> &'static str {
+    match status {
+        ProgramStatus::OnAir => "放送中",
+        ProgramStatus::Ended => "終了",
+        ProgramStatus::Reserved => "放送予定",
+        ProgramStatus::Unknown => "不明",
+    }
+}
+
+fn provider_label(provider_type: ProviderType) -> &'static str {
+    match provider_type {
+        ProviderType::Community => "ユーザー",
+        ProviderType::Channel => "チャンネル",
+        ProviderType::Official => "公式",
+        ProviderType::Unknown => "不明",
+    }
+}
+
+fn format_duration(seconds: i64) -> String {
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
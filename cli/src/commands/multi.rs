@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use crossterm::event::{Event as CEvent, KeyCode, KeyEvent};
+use ndgr_client::client::NdgrClient;
+use ndgr_client::comment_buffer::CommentBuffer;
+use ndgr_client::model::{Event, Message};
+use ndgr_client::multi::{MultiWatcher, ProgramEvent, WatchEvent};
+use ndgr_client::state::StateTracker;
+use ndgr_client::websocket::WebSocketEvent;
+use ratatui::Frame;
+use ratatui::layout::{self, Constraint};
+use ratatui::text::Line;
+use ratatui::widgets::Paragraph;
+use tokio::select;
+use unicode_width::UnicodeWidthStr;
+
+use crate::commands::tui::{TerminalGuard, fit_width, spawn_input_reader};
+
+#[derive(Debug, Args)]
+pub struct MultiArgs {
+    /// 視聴ページの URL、番組 ID (lv...)、放送中のコミュニティ・チャンネル ID (co... / ch...)
+    #[arg(required = true, num_args = 1..)]
+    programs: Vec<String>,
+    /// 画面の分け方
+    #[arg(long, value_enum, default_value_t)]
+    layout: Layout,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum Layout {
+    /// 番組ごとに上下に区切った枠に表示する
+    #[default]
+    Split,
+    /// すべての番組のコメントを番組 ID を付けて 1 つの枠に流す
+    Interleaved,
+}
+
+/// 1 番組分の表示
+struct Pane {
+    program_id: String,
+    title: String,
+    state_tracker: StateTracker,
+    /// `Layout::Interleaved` では使わない
+    comments: CommentBuffer,
+}
+
+impl Pane {
+    /// 番組名と状態。幅に収まらなければ番組名を切り詰めて、状態は残す
+    fn header(&self, width: usize) -> String {
+        let state = self.state_tracker.state().to_string();
+        let title_width = width.saturating_sub(state.width_cjk() + 3);
+        format!("[{}] {state}", fit_width(&self.title, title_width))
+    }
+
+    /// 状態の変化は取り込み、コメント欄に出す行があれば返す
+    fn apply(&mut self, event: WatchEvent) -> Option<String> {
+        match event {
+            WatchEvent::Message(message) => match Message::from_chunked_message(&message) {
+                Some(Message {
+                    event: Event::StateChange(change),
+                    ..
+                }) => {
+                    self.state_tracker.apply(&change);
+                    None
+                }
+                Some(message) if message.event.is_comment() => Some(message.to_string()),
+                _ => None,
+            },
+            WatchEvent::ProgramEnded => Some("番組が終了しました".to_string()),
+            WatchEvent::StreamError(e) => Some(format!("受信エラー: {e}")),
+            WatchEvent::WebSocket(WebSocketEvent::Disconnected { reason }) => {
+                Some(format!("切断されました: {reason}"))
+            }
+            WatchEvent::WebSocket(WebSocketEvent::Reconnecting { wait_time_sec }) => {
+                Some(format!("{wait_time_sec}秒後に再接続します"))
+            }
+            WatchEvent::WebSocket(WebSocketEvent::Error { message }) => {
+                Some(format!("エラー: {message}"))
+            }
+            WatchEvent::WebSocket(WebSocketEvent::Closed) => {
+                Some("WebSocket が閉じられました".to_string())
+            }
+        }
+    }
+}
+
+/// 端末の大きさから、`Layout::Split` の 1 番組分の行数 (見出しを含む) と
+/// `Layout::Interleaved` のコメント欄の行数を決める
+fn pane_heights(height: usize, programs: usize) -> (usize, usize) {
+    // 番組ごとに見出し 1 行とコメント 1 行以上、最下行に操作の案内
+    let pane_height = height.saturating_sub(1) / programs.max(1);
+    let feed_height = height.saturating_sub(programs + 1);
+    (pane_height, feed_height)
+}
+
+/// 画面全体の状態
+struct Screen {
+    layout: Layout,
+    panes: Vec<Pane>,
+    /// 全番組で共有するコメント欄 (`Layout::Interleaved`)。下に番組ごとの状態を 1 行ずつ出す
+    feed: CommentBuffer,
+    pane_height: usize,
+    feed_height: usize,
+}
+
+impl Screen {
+    fn resize(&mut self, width: u16, height: u16) {
+        let width = width as usize;
+        (self.pane_height, self.feed_height) = pane_heights(height as usize, self.panes.len());
+        for pane in &mut self.panes {
+            pane.comments
+                .resize(width, self.pane_height.saturating_sub(1));
+        }
+        self.feed.resize(width, self.feed_height);
+    }
+
+    fn apply(&mut self, event: ProgramEvent) {
+        let Some(pane) = self
+            .panes
+            .iter_mut()
+            .find(|pane| pane.program_id == event.program_id)
+        else {
+            return;
+        };
+        if let Some(line) = pane.apply(event.event) {
+            match self.layout {
+                Layout::Split => pane.comments.push(line),
+                Layout::Interleaved => self.feed.push(format!("[{}] {line}", pane.program_id)),
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [body_area, help_area] =
+            layout::Layout::vertical([Constraint::Fill(1), Constraint::Length(1)])
+                .areas(frame.area());
+
+        match self.layout {
+            Layout::Split => {
+                let areas = layout::Layout::vertical(
+                    self.panes
+                        .iter()
+                        .map(|_| Constraint::Length(self.pane_height as u16)),
+                )
+                .split(body_area);
+                for (pane, area) in self.panes.iter().zip(areas.iter()) {
+                    let mut lines = vec![Line::from(pane.header(area.width as usize))];
+                    lines.extend(comment_lines(&pane.comments));
+                    frame.render_widget(Paragraph::new(lines), *area);
+                }
+            }
+            Layout::Interleaved => {
+                let [feed_area, status_area] = layout::Layout::vertical([
+                    Constraint::Length(self.feed_height as u16),
+                    Constraint::Fill(1),
+                ])
+                .areas(body_area);
+                frame.render_widget(Paragraph::new(comment_lines(&self.feed)), feed_area);
+                let statuses: Vec<Line> = self
+                    .panes
+                    .iter()
+                    .map(|pane| {
+                        let width = (status_area.width as usize)
+                            .saturating_sub(pane.program_id.width_cjk() + 1);
+                        Line::from(format!("{} {}", pane.program_id, pane.header(width)))
+                    })
+                    .collect();
+                frame.render_widget(Paragraph::new(statuses), status_area);
+            }
+        }
+
+        frame.render_widget(Line::from("Esc で終了"), help_area);
+    }
+}
+
+fn comment_lines(comments: &CommentBuffer) -> Vec<Line<'_>> {
+    comments
+        .visible()
+        .map(|(_, line)| Line::from(line.text.as_str()))
+        .collect()
+}
+
+pub async fn run(client: &NdgrClient, args: MultiArgs) -> Result<()> {
+    let (width, height) = crossterm::terminal::size()?;
+    let (pane_height, feed_height) = pane_heights(height as usize, args.programs.len());
+    if args.layout == Layout::Split && pane_height < 2 {
+        anyhow::bail!(
+            "端末の高さが足りません ({} 番組には {} 行以上必要)",
+            args.programs.len(),
+            args.programs.len() * 2 + 1
+        );
+    }
+
+    let mut watcher = MultiWatcher::new(client.clone());
+    let mut panes = Vec::new();
+    for program in &args.programs {
+        let url = client.resolve_watch_url(program).await?;
+        let info = watcher.add(&url).await?;
+        panes.push(Pane {
+            program_id: info.program.nicolive_program_id.clone(),
+            title: info.program.title.clone(),
+            state_tracker: StateTracker::new(),
+            comments: CommentBuffer::new(width as usize, pane_height.saturating_sub(1)),
+        });
+    }
+    let mut screen = Screen {
+        layout: args.layout,
+        panes,
+        feed: CommentBuffer::new(width as usize, feed_height),
+        pane_height,
+        feed_height,
+    };
+
+    // 端末は抜けるときに戻し、エラーで抜けてもセッションは閉じる
+    let result = async {
+        let mut terminal = TerminalGuard::enter()?;
+        let mut input = spawn_input_reader();
+
+        loop {
+            terminal.draw(|frame| screen.draw(frame))?;
+
+            select! {
+                event = watcher.next_event() => screen.apply(event),
+                Some(event) = input.recv() => {
+                    match event.context("failed to read terminal input")? {
+                        CEvent::Key(KeyEvent { code: KeyCode::Esc, .. }) => break,
+                        CEvent::Resize(width, height) => screen.resize(width, height),
+                        _ => {}
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    watcher.shutdown().await;
+    result
+}
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Args;
+use futures::StreamExt;
+use ndgr_client::StreamItem;
+use ndgr_client::archive::{ArchiveWriter, Recorder};
+use ndgr_client::client::NdgrClient;
+use ndgr_client::resume::StreamResume;
+use ndgr_client::websocket::WebSocketClient;
+use tokio::select;
+
+use crate::commands::{ProgramArgs, TimeshiftArgs, open_stream};
+
+#[derive(Debug, Args)]
+pub struct RecordArgs {
+    #[command(flatten)]
+    program: ProgramArgs,
+    #[command(flatten)]
+    timeshift: TimeshiftArgs,
+    /// 保存先のファイル
+    #[arg(short, long)]
+    output: PathBuf,
+    /// zstd で圧縮する。レベルを省略すると 3
+    #[arg(long, value_name = "LEVEL", num_args = 0..=1, default_missing_value = "3")]
+    zstd: Option<i32>,
+    /// 接続より前のコメントをこの件数まで記録する (タイムシフトでは無視)
+    #[arg(long, default_value_t = 0)]
+    history: usize,
+}
+
+/// 番組が終わるか Ctrl-C で止めるまで、受信した生のバイト列をアーカイブに書き込む
+pub async fn run(client: &NdgrClient, args: RecordArgs) -> Result<()> {
+    let info = args.program.fetch_info(client).await?;
+
+    let recorder = Recorder::new(ArchiveWriter::create(&args.output, args.zstd)?);
+    let client = client.with_recorder(recorder.clone());
+    let timeshift = args.timeshift.options(&info, &client);
+
+    let web_socket_client =
+        WebSocketClient::connect(&client, &info.site.relive.web_socket_url).await?;
+    let mut view_uri_rx = web_socket_client.subscribe_view_uri();
+    let view_uri = view_uri_rx.borrow_and_update().clone();
+
+    if args.history > 0 && timeshift.is_none() {
+        client.fetch_history(&view_uri, args.history).await?;
+    }
+
+    let mut resume = StreamResume::new();
+    let mut stream = open_stream(&client, &view_uri, timeshift.as_ref(), &resume).await;
+    let mut count = 0;
+
+    loop {
+        select! {
+            item = stream.next() => {
+                match item {
+                    Some(Ok(StreamItem::Message(message))) => {
+                        resume.record(&message);
+                        count += 1;
+                    }
+                    Some(Ok(StreamItem::ProgramEnded)) | None => break,
+                    Some(Err(e)) => eprintln!("{e}"),
+                }
+            }
+            Ok(()) = view_uri_rx.changed() => {
+                // 再接続でメッセージサーバーが変わったら、続きからストリームを張り直す
+                let view_uri = view_uri_rx.borrow_and_update().clone();
+                stream = open_stream(&client, &view_uri, timeshift.as_ref(), &resume).await;
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    web_socket_client.shutdown().await;
+    recorder.finish().await?;
+    eprintln!("recorded {count} messages to {}", args.output.display());
+    Ok(())
+}
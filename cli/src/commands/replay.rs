@@ -0,0 +1,54 @@
+use std::io::stdout;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Args;
+use futures::StreamExt;
+use ndgr_client::StreamItem;
+use ndgr_client::replay::{self, Pacing, ReplayOptions};
+
+use crate::commands::dump::{OutputArgs, ignore_broken_pipe};
+use crate::commands::{OutputFormat, parse_speed};
+
+#[derive(Debug, Args)]
+pub struct ReplayArgs {
+    /// record で保存したファイル
+    input: PathBuf,
+    /// 再生速度 (記録したときの間隔を何倍速で流すか)
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed, conflicts_with = "fast")]
+    speed: f64,
+    /// 待たずにすべて流す
+    #[arg(long)]
+    fast: bool,
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+pub async fn run(args: ReplayArgs, format: OutputFormat) -> Result<()> {
+    let pacing = if args.fast {
+        Pacing::AsFastAsPossible
+    } else {
+        Pacing::Speed(args.speed)
+    };
+    let options = ReplayOptions {
+        pacing,
+        ..ReplayOptions::default()
+    };
+    let stream = replay::open_with_options(&args.input, options).await?;
+    let mut stream = std::pin::pin!(stream);
+
+    let mut writer = args.output.writer(stdout().lock(), format);
+    let result = async {
+        writer.write_header()?;
+        while let Some(item) = stream.next().await {
+            match item? {
+                StreamItem::Message(message) => writer.write(&message)?,
+                StreamItem::ProgramEnded => break,
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    ignore_broken_pipe(result)
+}
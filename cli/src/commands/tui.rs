@@ -0,0 +1,82 @@
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+use anyhow::Result;
+use crossterm::event::{self, Event as CEvent};
+use ratatui::DefaultTerminal;
+use tokio::sync::mpsc;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// 入力を待つ間隔。受け取る側がいなくなったかをこの間隔で確かめる
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 生きている間は端末を raw モードと代替画面にする。
+/// エラーで抜けたときも panic したときも、drop で元に戻す。
+pub struct TerminalGuard {
+    terminal: DefaultTerminal,
+}
+
+impl TerminalGuard {
+    pub fn enter() -> Result<Self> {
+        // ratatui の panic フックで、メッセージを出す前に端末を戻す
+        let terminal = ratatui::try_init()?;
+        Ok(Self { terminal })
+    }
+}
+
+impl Deref for TerminalGuard {
+    type Target = DefaultTerminal;
+
+    fn deref(&self) -> &Self::Target {
+        &self.terminal
+    }
+}
+
+impl DerefMut for TerminalGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.terminal
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = self.terminal.show_cursor();
+        ratatui::restore();
+    }
+}
+
+/// キー入力と端末の大きさの変化を別スレッドで読んで送る。
+/// 読み取りに失敗したらそのエラーを送って終わる。
+pub fn spawn_input_reader() -> mpsc::UnboundedReceiver<io::Result<CEvent>> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while !tx.is_closed() {
+            let event = event::poll(INPUT_POLL_INTERVAL)
+                .and_then(|ready| ready.then(event::read).transpose());
+            match event {
+                Ok(Some(event @ (CEvent::Key(_) | CEvent::Resize(..)))) => {
+                    let _ = tx.send(Ok(event));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                    break;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// 表示幅が `width` を超えないように、書記素クラスタの区切りで切り詰める
+pub fn fit_width(text: &str, width: usize) -> String {
+    let mut current_width = 0;
+    text.graphemes(true)
+        .take_while(|grapheme| {
+            current_width += grapheme.width_cjk();
+            current_width <= width
+        })
+        .collect()
+}
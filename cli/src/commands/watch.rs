@@ -0,0 +1,532 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use crossterm::event::{Event as CEvent, KeyCode, KeyEvent, KeyModifiers};
+use futures::StreamExt;
+use ndgr_client::StreamItem;
+use ndgr_client::client::NdgrClient;
+use ndgr_client::comment_buffer::{Comment, CommentBuffer, DEFAULT_SCROLLBACK, SearchDirection};
+use ndgr_client::history::History;
+use ndgr_client::model::{Event, Message};
+use ndgr_client::program_info::ProgramInfo;
+use ndgr_client::resume::StreamResume;
+use ndgr_client::state::StateTracker;
+use ndgr_client::websocket::{
+    ConnectionState, PostComment, PostCommentResult, SessionStatus, WebSocketClient,
+    WebSocketEvent, vpos_since,
+};
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Paragraph, Row, Table};
+use tokio::select;
+use tokio::sync::{mpsc, watch};
+
+use crate::commands::tui::{TerminalGuard, spawn_input_reader};
+use crate::commands::{ProgramArgs, TimeshiftArgs, open_stream};
+
+/// 接続時に表示する過去コメントの件数
+const HISTORY_LIMIT: usize = 100;
+
+/// タイムシフト再生で ← / → を押したときに移動する秒数
+const SEEK_STEP_SEC: i64 = 30;
+
+/// コメント欄の時刻・コメント番号・ユーザー名の列幅。残りの幅を本文に使う
+const COLUMN_WIDTHS: [u16; 3] = [8, 6, 14];
+
+/// 番組情報 2 行・コメント欄の見出し 1 行・ステータスバー 1 行・入力欄 1 行
+const CHROME_HEIGHT: u16 = 5;
+
+/// 端末の大きさから、コメント欄の本文の幅と行数を決める
+fn comment_area(width: u16, height: u16) -> (usize, usize) {
+    // 固定の列と列の間の空白を除いた分が本文
+    let columns_width: u16 = COLUMN_WIDTHS.iter().map(|width| width + 1).sum();
+    (
+        width.saturating_sub(columns_width) as usize,
+        height.saturating_sub(CHROME_HEIGHT) as usize,
+    )
+}
+
+/// `text` のうち `query` に一致する部分を反転表示にする
+fn highlight<'a>(text: &'a str, query: &str) -> Line<'a> {
+    let mut spans = Vec::new();
+    let mut rest = text;
+    while !query.is_empty()
+        && let Some(i) = rest.find(query)
+    {
+        spans.push(Span::raw(&rest[..i]));
+        spans.push(Span::raw(&rest[i..i + query.len()]).reversed());
+        rest = &rest[i + query.len()..];
+    }
+    spans.push(Span::raw(rest));
+    Line::from(spans)
+}
+
+/// 番組開始からの経過時間 (`H:MM:SS`)
+fn format_elapsed(sec: i64) -> String {
+    let sign = if sec < 0 { "-" } else { "" };
+    let sec = sec.abs();
+    format!("{sign}{}:{:02}:{:02}", sec / 3600, sec / 60 % 60, sec % 60)
+}
+
+/// コメント欄に流す行。チャットは時刻・コメント番号・ユーザー名の列を付ける
+fn comment_row(message: &Message, begin_time: i64) -> Comment {
+    let time = message
+        .at
+        .map(|at| format_elapsed(at - begin_time))
+        .unwrap_or_default();
+    match &message.event {
+        Event::Chat(chat) => Comment {
+            columns: vec![time, chat.no.to_string(), chat.user_label()],
+            text: chat.content.clone(),
+        },
+        event => Comment {
+            columns: vec![time, String::new(), String::new()],
+            text: event.to_string(),
+        },
+    }
+}
+
+/// `/` で始めたインクリメンタル検索
+struct Search {
+    query: String,
+    /// 入力中なら `true`。Enter で確定すると一致箇所の強調だけが残る
+    editing: bool,
+    /// 検索を始めたときの表示位置。ここから古い方へ探し、取り消したらここに戻る
+    anchor_offset: usize,
+    anchor_line: usize,
+    /// いま表示している一致行
+    current: Option<usize>,
+}
+
+impl Search {
+    fn new(comment_buffer: &CommentBuffer) -> Self {
+        Self {
+            query: String::new(),
+            editing: true,
+            anchor_offset: comment_buffer.offset(),
+            anchor_line: comment_buffer.bottom_line(),
+            current: None,
+        }
+    }
+
+    /// 入力が変わったら、検索を始めた位置から探し直す
+    fn update(&mut self, comment_buffer: &mut CommentBuffer) {
+        comment_buffer.set_offset(self.anchor_offset);
+        self.current =
+            comment_buffer.find(&self.query, self.anchor_line, SearchDirection::Backward);
+        if let Some(line) = self.current {
+            comment_buffer.reveal(line);
+        }
+    }
+
+    /// 入力中のキー操作。検索を取り消したら `false`
+    fn handle_key(&mut self, key: KeyEvent, comment_buffer: &mut CommentBuffer) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => {
+                comment_buffer.set_offset(self.anchor_offset);
+                return false;
+            }
+            KeyCode::Enter => self.editing = false,
+            KeyCode::Up => self.step(comment_buffer, SearchDirection::Backward),
+            KeyCode::Down => self.step(comment_buffer, SearchDirection::Forward),
+            KeyCode::Char('p') if ctrl => self.step(comment_buffer, SearchDirection::Backward),
+            KeyCode::Char('n') if ctrl => self.step(comment_buffer, SearchDirection::Forward),
+            KeyCode::Char(c) if !ctrl => {
+                self.query.push(c);
+                self.update(comment_buffer);
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.update(comment_buffer);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// 折り返し直して行番号が変わったので、いまの表示位置から探し直せるようにする
+    fn rebase(&mut self, comment_buffer: &CommentBuffer) {
+        self.anchor_offset = comment_buffer.offset();
+        self.anchor_line = comment_buffer.bottom_line();
+        self.current = None;
+    }
+
+    /// いまの一致行より古い (`Backward`) か新しい (`Forward`) 一致行へ移る
+    fn step(&mut self, comment_buffer: &mut CommentBuffer, direction: SearchDirection) {
+        let from = match (self.current, direction) {
+            (Some(line), SearchDirection::Backward) => line.saturating_sub(1),
+            (Some(line), SearchDirection::Forward) => line + 1,
+            (None, _) => self.anchor_line,
+        };
+        if let Some(line) = comment_buffer.find(&self.query, from, direction) {
+            self.current = Some(line);
+            comment_buffer.reveal(line);
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    #[command(flatten)]
+    program: ProgramArgs,
+    #[command(flatten)]
+    timeshift: TimeshiftArgs,
+    /// さかのぼって表示できるように保持する行数
+    #[arg(long, default_value_t = DEFAULT_SCROLLBACK)]
+    scrollback: usize,
+}
+
+/// 画面に出す状態
+struct App {
+    info: ProgramInfo,
+    comment_buffer: CommentBuffer,
+    state_tracker: StateTracker,
+    status: watch::Receiver<SessionStatus>,
+    /// 最後に受け取ったメッセージの時刻。経過時間の表示に使う
+    latest_at: Option<i64>,
+    /// タイムシフトの再生位置 (最後に表示したメッセージの時刻)
+    position: Option<i64>,
+    stream_finished: bool,
+    input: String,
+    search: Option<Search>,
+}
+
+impl App {
+    fn apply_message(&mut self, message: Message) {
+        if message.at.is_some() {
+            self.latest_at = message.at;
+        }
+        match message.event {
+            Event::StateChange(change) => {
+                self.state_tracker.apply(&change);
+            }
+            ref event if event.is_comment() => {
+                let row = comment_row(&message, self.info.program.begin_time);
+                self.comment_buffer.push(row);
+            }
+            _ => {}
+        }
+    }
+
+    fn resize(&mut self, width: u16, height: u16) {
+        let (width, height) = comment_area(width, height);
+        self.comment_buffer.resize(width, height);
+        if let Some(search) = &mut self.search {
+            search.rebase(&self.comment_buffer);
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header_area, comments_area, status_area, input_area] = Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let elapsed = self
+            .latest_at
+            .map(|at| format_elapsed(at - self.info.program.begin_time))
+            .unwrap_or_else(|| "-".to_string());
+        let header = Paragraph::new(vec![
+            Line::from(vec![
+                Span::raw(self.info.program.title.as_str()).bold(),
+                Span::raw(format!(" ({})", self.info.program.nicolive_program_id)).dim(),
+            ]),
+            Line::from(format!("{} / 経過 {elapsed}", self.state_tracker.state())),
+        ]);
+        frame.render_widget(header, header_area);
+
+        let query = self
+            .search
+            .as_ref()
+            .map_or("", |search| search.query.as_str());
+        let rows = self.comment_buffer.visible().map(|(_, line)| {
+            let mut cells: Vec<Line> = (0..COLUMN_WIDTHS.len())
+                .map(|i| {
+                    let column = line.columns.get(i).map_or("", String::as_str);
+                    highlight(column, query)
+                })
+                .collect();
+            cells.push(highlight(&line.text, query));
+            Row::new(cells)
+        });
+        let widths = COLUMN_WIDTHS
+            .map(Constraint::Length)
+            .into_iter()
+            .chain([Constraint::Fill(1)]);
+        let table = Table::new(rows, widths)
+            .header(Row::new(["時刻", "No", "ユーザー", "コメント"]).underlined());
+        frame.render_widget(table, comments_area);
+
+        frame.render_widget(
+            Paragraph::new(self.status_line()).style(Style::new().reversed()),
+            status_area,
+        );
+
+        let input = match &self.search {
+            Some(search) if search.editing => {
+                let not_found = if search.current.is_none() && !search.query.is_empty() {
+                    " (見つかりません)"
+                } else {
+                    ""
+                };
+                format!("検索: {}{not_found}", search.query)
+            }
+            _ => format!("コメント入力: {}", self.input),
+        };
+        let input = Line::from(input);
+        let cursor_x = input_area.x + input.width().min(input_area.width as usize) as u16;
+        frame.render_widget(input, input_area);
+        // 入力欄が入らないほど小さい端末ではカーソルを出さない
+        if input_area.height > 0 {
+            frame.set_cursor_position((cursor_x, input_area.y));
+        }
+    }
+
+    /// 接続・座席・再生位置・スクロールの状態
+    fn status_line(&self) -> String {
+        let status = self.status.borrow();
+        let mut items = vec![match &status.connection {
+            ConnectionState::Connected => "接続中".to_string(),
+            ConnectionState::Reconnecting { wait_time_sec } => {
+                format!("{wait_time_sec}秒後に再接続")
+            }
+            ConnectionState::Disconnected { reason } => format!("切断: {reason}"),
+            ConnectionState::Closed => "切断".to_string(),
+        }];
+        if status.keep_interval_sec > 0 {
+            let last = status
+                .last_keep_seat
+                .and_then(|at| SystemTime::now().duration_since(at).ok())
+                .map(|elapsed| format!(" (前回 {}秒前)", elapsed.as_secs()))
+                .unwrap_or_default();
+            items.push(format!("座席維持 {}秒ごと{last}", status.keep_interval_sec));
+        }
+        if self.stream_finished {
+            items.push("受信終了".to_string());
+        }
+        if let Some(position) = self.position {
+            items.push(format!(
+                "再生位置 {}",
+                format_elapsed(position - self.info.program.begin_time)
+            ));
+        }
+        if self.comment_buffer.is_paused() {
+            items.push("一時停止中".to_string());
+        }
+        if self.comment_buffer.offset() > 0 {
+            items.push(format!("下に {} 行", self.comment_buffer.offset()));
+        }
+        items.join(" / ")
+    }
+}
+
+pub async fn run(client: &NdgrClient, args: WatchArgs) -> Result<()> {
+    let info = args.program.fetch_info(client).await?;
+    let mut timeshift = args.timeshift.options(&info, client);
+
+    let web_socket_client =
+        WebSocketClient::connect(client, &info.site.relive.web_socket_url).await?;
+    let mut view_uri_rx = web_socket_client.subscribe_view_uri();
+    let mut events = web_socket_client.subscribe_events();
+    let view_uri = view_uri_rx.borrow_and_update().clone();
+
+    // 過去コメントが取れなくても視聴は続ける。タイムシフトでは再生位置から流すので取らない
+    let history = if timeshift.is_none() {
+        client
+            .fetch_history(&view_uri, HISTORY_LIMIT)
+            .await
+            .unwrap_or_default()
+    } else {
+        History::default()
+    };
+
+    let mut resume = StreamResume::new();
+    resume.extend_seen(history.message_ids());
+    let mut stream = open_stream(client, &view_uri, timeshift.as_ref(), &resume).await;
+
+    // 端末は抜けるときに戻し、エラーで抜けても WebSocket は閉じる
+    let result = async {
+        let mut terminal = TerminalGuard::enter()?;
+        let size = terminal.size()?;
+        let (width, height) = comment_area(size.width, size.height);
+        let mut app = App {
+            comment_buffer: CommentBuffer::with_scrollback(width, height, args.scrollback),
+            state_tracker: StateTracker::new(),
+            status: web_socket_client.subscribe_status(),
+            latest_at: None,
+            position: timeshift.as_ref().map(|options| options.start_at),
+            stream_finished: false,
+            input: String::new(),
+            search: None,
+            info,
+        };
+
+        for message in &history.snapshot {
+            app.state_tracker.apply_message(message);
+        }
+        for message in &history.messages {
+            if let Some(message) = Message::from_chunked_message(message) {
+                app.apply_message(message);
+            }
+        }
+
+        let mut input = spawn_input_reader();
+
+        let poster = web_socket_client.poster();
+        let (post_result_tx, mut post_result_rx) =
+            mpsc::unbounded_channel::<Result<PostCommentResult>>();
+        // 座席維持の「前回 N 秒前」を進めるために 1 秒ごとに描き直す
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+
+        loop {
+            terminal.draw(|frame| app.draw(frame))?;
+
+            select! {
+                message = stream.next(), if !app.stream_finished => {
+                    match message {
+                        Some(Ok(StreamItem::Message(message))) => {
+                            resume.record(&message);
+                            if app.position.is_some() && let Some(at) = resume.last_at() {
+                                app.position = Some(at as i64);
+                            }
+                            if let Some(message) = Message::from_chunked_message(&message) {
+                                app.apply_message(message);
+                            }
+                        },
+                        Some(Ok(StreamItem::ProgramEnded)) => {
+                            app.comment_buffer.push("番組が終了しました".to_string());
+                        },
+                        Some(Err(e)) => {
+                            app.comment_buffer.push(format!("受信エラー: {}", e));
+                        },
+                        // 番組終了や再試行の打ち切りで終わった後も Esc までは画面を残す
+                        None => app.stream_finished = true,
+                    }
+                },
+                Ok(()) = view_uri_rx.changed() => {
+                    // 再接続でメッセージサーバーが変わったら、続きからストリームを張り直す
+                    let view_uri = view_uri_rx.borrow_and_update().clone();
+                    stream = open_stream(client, &view_uri, timeshift.as_ref(), &resume).await;
+                    app.stream_finished = false;
+                },
+                Ok(event) = events.recv() => {
+                    // 接続の状態はステータスバーに出すので、エラーだけをコメント欄に流す
+                    if let WebSocketEvent::Error { message } = event {
+                        app.comment_buffer.push(format!("エラー: {}", message));
+                    }
+                },
+                Some(result) = post_result_rx.recv() => {
+                    match result {
+                        Ok(result) => {
+                            app.comment_buffer.push(format!("投稿しました: {}", result.chat.content));
+                        },
+                        Err(e) => app.comment_buffer.push(format!("投稿に失敗しました: {}", e)),
+                    }
+                },
+                _ = tick.tick() => {},
+                Some(event) = input.recv() => {
+                    let key = match event.context("failed to read terminal input")? {
+                        CEvent::Key(key) => key,
+                        CEvent::Resize(width, height) => {
+                            app.resize(width, height);
+                            continue;
+                        },
+                        _ => continue,
+                    };
+                    let comment_buffer = &mut app.comment_buffer;
+                    if let Some(editing) = app.search.as_mut().filter(|search| search.editing) {
+                        if !editing.handle_key(key, comment_buffer) {
+                            app.search = None;
+                        }
+                        continue;
+                    }
+
+                    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+                    match key.code {
+                        KeyCode::Char('s') if ctrl => {
+                            comment_buffer.set_paused(!comment_buffer.is_paused());
+                        },
+                        KeyCode::Char('f') if ctrl => {
+                            app.search = Some(Search::new(comment_buffer));
+                        },
+                        KeyCode::Char('/') if app.input.is_empty() => {
+                            app.search = Some(Search::new(comment_buffer));
+                        },
+                        // 確定した検索の一致行を古い方 (Ctrl-P) / 新しい方 (Ctrl-N) へたどる
+                        KeyCode::Char(c @ ('p' | 'n')) if ctrl && app.search.is_some() => {
+                            let direction = if c == 'p' {
+                                SearchDirection::Backward
+                            } else {
+                                SearchDirection::Forward
+                            };
+                            if let Some(search) = &mut app.search {
+                                search.step(comment_buffer, direction);
+                            }
+                        },
+                        KeyCode::Char(c) if !ctrl => {
+                            app.input.push(c);
+                        },
+                        KeyCode::Backspace => {
+                            app.input.pop();
+                        },
+                        KeyCode::Enter if !app.input.is_empty() => {
+                            let comment = PostComment::new(
+                                std::mem::take(&mut app.input),
+                                vpos_since(app.info.program.vpos_base_time),
+                            );
+                            let poster = poster.clone();
+                            let post_result_tx = post_result_tx.clone();
+                            tokio::spawn(async move {
+                                let _ = post_result_tx.send(poster.post(comment).await);
+                            });
+                        },
+                        KeyCode::Up => comment_buffer.scroll_up(1),
+                        KeyCode::Down => comment_buffer.scroll_down(1),
+                        KeyCode::PageUp => comment_buffer.page_up(),
+                        KeyCode::PageDown => comment_buffer.page_down(),
+                        KeyCode::Home => comment_buffer.scroll_to_top(),
+                        KeyCode::End => comment_buffer.scroll_to_bottom(),
+                        KeyCode::Left | KeyCode::Right if timeshift.is_some() => {
+                            let step = match key.code {
+                                KeyCode::Left => -SEEK_STEP_SEC,
+                                _ => SEEK_STEP_SEC,
+                            };
+                            if let (Some(options), Some(at)) = (&mut timeshift, app.position) {
+                                let begin_time = app.info.program.begin_time;
+                                options.start_at = (at + step).max(begin_time);
+                                app.position = Some(options.start_at);
+                            }
+                            // シークした位置からは、流したメッセージも流し直す
+                            resume = StreamResume::new();
+                            let view_uri = view_uri_rx.borrow().clone();
+                            let options = timeshift.as_ref();
+                            stream = open_stream(client, &view_uri, options, &resume).await;
+                            app.stream_finished = false;
+                        },
+                        // 検索の強調表示が残っていれば先に消す
+                        KeyCode::Esc if app.search.is_some() => {
+                            app.search = None;
+                        },
+                        KeyCode::Esc => {
+                            break;
+                        },
+                        _ => {}
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+    .await;
+
+    web_socket_client.shutdown().await;
+    result
+}
@@ -1,49 +1,324 @@
 use std::collections::VecDeque;
 
-use unicode_width::UnicodeWidthChar;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
+/// [`CommentBuffer::new`] で保持する行数
+pub const DEFAULT_SCROLLBACK: usize = 10_000;
+
+/// コメント欄に流す 1 件
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Comment {
+    /// 時刻・コメント番号・ユーザー名のように、本文の前に並べる列
+    pub columns: Vec<String>,
+    /// 折り返す本文
+    pub text: String,
+}
+
+impl From<String> for Comment {
+    fn from(text: String) -> Self {
+        Self {
+            columns: Vec::new(),
+            text,
+        }
+    }
+}
+
+/// 折り返した後の 1 行
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WrappedLine {
+    /// [`Comment::columns`]。折り返した 2 行目以降は空
+    pub columns: Vec<String>,
+    pub text: String,
+    /// 元のコメントの通し番号
+    comment: usize,
+}
+
+/// `text` を表示幅 `width` ごとに区切る。書記素クラスタ (結合文字や絵文字の ZWJ 連結) は分けない。
+/// 1 つで `width` を超えるクラスタはそれだけで 1 行にする。
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut wrapped = Vec::new();
+    let mut current_line = String::new();
+    let mut current_width = 0;
+
+    for grapheme in text.graphemes(true) {
+        // 改行やタブは端末上の幅が決まらないので空白にする
+        let grapheme = if grapheme.chars().any(char::is_control) {
+            " "
+        } else {
+            grapheme
+        };
+        let grapheme_width = grapheme.width_cjk();
+
+        if current_width + grapheme_width > width && !current_line.is_empty() {
+            wrapped.push(std::mem::take(&mut current_line));
+            current_width = 0;
+        }
+
+        current_line.push_str(grapheme);
+        current_width += grapheme_width;
+    }
+
+    if !current_line.is_empty() {
+        wrapped.push(current_line);
+    }
+    wrapped
+}
+
+/// コメントを折り返した行を、スクロールバックの分まで保持する。
+/// 折り返す前のコメントも持っておき、幅が変わったら折り返し直す。
+/// 行番号は捨てた行も数えた通し番号で、新しい行が増えても変わらない
+/// (幅を変えたときは、残っている先頭の行を 0 として振り直す)。
 pub struct CommentBuffer {
-    comments: VecDeque<String>,
+    /// `lines` に残っている分の、折り返す前のコメント
+    comments: VecDeque<Comment>,
+    /// これまでに捨てたコメントの数 (= 先頭のコメントの通し番号)
+    dropped_comments: usize,
+    lines: VecDeque<WrappedLine>,
+    /// これまでに捨てた行数 (= 先頭の行の行番号)
+    dropped: usize,
     width: usize,
     height: usize,
+    scrollback: usize,
+    /// 最下行から何行さかのぼって表示しているか。0 なら最新の行まで表示する
+    offset: usize,
+    paused: bool,
+}
+
+/// [`CommentBuffer::find`] で探す向き
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchDirection {
+    /// 古い行へ
+    Backward,
+    /// 新しい行へ
+    Forward,
 }
 
 impl CommentBuffer {
+    /// [`DEFAULT_SCROLLBACK`] 行まで保持する
     pub fn new(width: usize, height: usize) -> Self {
+        Self::with_scrollback(width, height, DEFAULT_SCROLLBACK)
+    }
+
+    /// `scrollback` 行まで保持する。表示する `height` 行より少なくはしない。
+    /// 幅は 1 桁未満にはしない。
+    pub fn with_scrollback(width: usize, height: usize, scrollback: usize) -> Self {
         Self {
             comments: VecDeque::new(),
-            width,
+            dropped_comments: 0,
+            lines: VecDeque::new(),
+            dropped: 0,
+            width: width.max(1),
             height,
+            scrollback,
+            offset: 0,
+            paused: false,
         }
     }
 
-    pub fn push(&mut self, comment: String) {
-        let mut current_line = String::new();
-        let mut current_width = 0;
+    /// 本文を `width` で折り返して追加する。本文も列もなければ何もしない
+    pub fn push(&mut self, comment: impl Into<Comment>) {
+        let comment = comment.into();
+        let lines = self.wrap_comment(&comment, self.dropped_comments + self.comments.len());
+        if lines.is_empty() {
+            return;
+        }
+        let added = lines.len();
+        self.lines.extend(lines);
+        self.comments.push_back(comment);
+
+        // さかのぼって見ている間と一時停止中は、表示している行を動かさない
+        if self.offset > 0 || self.paused {
+            self.offset += added;
+        }
 
-        for c in comment.chars() {
-            let c_width = c.width_cjk().unwrap_or(0);
+        self.trim();
+        self.offset = self.offset.min(self.max_offset());
+    }
 
-            if current_width + c_width > self.width {
-                self.comments.push_back(current_line.clone());
-                current_line.clear();
-                current_width = 0;
+    /// 表示する大きさを変える。幅が変わったら保持しているコメントをすべて折り返し直し、
+    /// 表示の最下行にあったコメントが最下行に来るようにする。
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let width = width.max(1);
+        self.height = height;
+        if width != self.width {
+            let bottom = self
+                .lines
+                .len()
+                .checked_sub(self.offset + 1)
+                .map(|i| self.lines[i].comment);
+            self.width = width;
+            // 捨てた行は前の幅で数えたものなので、番号を引き継がない
+            self.dropped = 0;
+            self.lines = self
+                .comments
+                .iter()
+                .zip(self.dropped_comments..)
+                .flat_map(|(comment, number)| self.wrap_comment(comment, number))
+                .collect();
+            self.trim();
+            if let Some(bottom) = bottom {
+                self.offset = self
+                    .lines
+                    .iter()
+                    .rev()
+                    .take_while(|line| line.comment > bottom)
+                    .count();
             }
+        }
+        self.offset = self.offset.min(self.max_offset());
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// 表示する行 (上から順に、行番号付き)
+    pub fn visible(&self) -> impl Iterator<Item = (usize, &WrappedLine)> {
+        let end = self.lines.len() - self.offset;
+        let start = end.saturating_sub(self.height);
+        (start..end).map(|i| (self.dropped + i, &self.lines[i]))
+    }
+
+    /// 保持しているすべての行
+    pub fn lines(&self) -> &VecDeque<WrappedLine> {
+        &self.lines
+    }
+
+    /// 表示の最下行より下にある行数
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// 最新の行まで表示していて、新しい行が来たら流れる状態か
+    pub fn is_following(&self) -> bool {
+        self.offset == 0 && !self.paused
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.offset = (self.offset + lines).min(self.max_offset());
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.offset = self.offset.saturating_sub(lines);
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll_up(self.height.max(1));
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll_down(self.height.max(1));
+    }
+
+    /// 保持している最も古い行まで戻る
+    pub fn scroll_to_top(&mut self) {
+        self.offset = self.max_offset();
+    }
+
+    /// 最新の行に戻る
+    pub fn scroll_to_bottom(&mut self) {
+        self.offset = 0;
+    }
+
+    /// 一時停止中は新しい行を溜めるだけで表示を動かさない。解除すると最新の行に戻る
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if !paused {
+            self.scroll_to_bottom();
+        }
+    }
 
-            current_line.push(c);
-            current_width += c_width;
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// `from` 行目 (含む) から `direction` の向きに、本文か列に `query` を含む行を探す
+    pub fn find(&self, query: &str, from: usize, direction: SearchDirection) -> Option<usize> {
+        if query.is_empty() || self.lines.is_empty() {
+            return None;
+        }
+        let from = from.saturating_sub(self.dropped);
+        let matches = |i: &usize| {
+            let line = &self.lines[*i];
+            line.text.contains(query) || line.columns.iter().any(|column| column.contains(query))
+        };
+        match direction {
+            SearchDirection::Backward => (0..=from.min(self.lines.len() - 1)).rev().find(matches),
+            SearchDirection::Forward => (from..self.lines.len()).find(matches),
         }
+        .map(|i| self.dropped + i)
+    }
+
+    /// 表示の最下行の行番号。行がなければ 0
+    pub fn bottom_line(&self) -> usize {
+        (self.dropped + self.lines.len()).saturating_sub(self.offset + 1)
+    }
 
-        if !current_line.is_empty() {
-            self.comments.push_back(current_line);
+    /// `line` 行目が表示範囲に入るように、必要な分だけスクロールする
+    pub fn reveal(&mut self, line: usize) {
+        let Some(index) = line.checked_sub(self.dropped) else {
+            return self.scroll_to_top();
+        };
+        let from_bottom = self.lines.len().saturating_sub(index + 1);
+        if from_bottom < self.offset {
+            self.offset = from_bottom;
+        } else if from_bottom >= self.offset + self.height {
+            self.offset = (from_bottom + 1).saturating_sub(self.height);
         }
+        self.offset = self.offset.min(self.max_offset());
+    }
 
-        while self.comments.len() > self.height {
-            self.comments.pop_front();
+    /// 表示の位置を `offset` に戻す (検索の取り消しなど)
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = offset.min(self.max_offset());
+    }
+
+    fn max_offset(&self) -> usize {
+        self.lines.len().saturating_sub(self.height)
+    }
+
+    fn wrap_comment(&self, comment: &Comment, number: usize) -> Vec<WrappedLine> {
+        let mut wrapped = wrap(&comment.text, self.width);
+        // 本文が空でも列があれば 1 行にする
+        if wrapped.is_empty() && !comment.columns.is_empty() {
+            wrapped.push(String::new());
         }
+        let mut columns = Some(comment.columns.clone());
+        wrapped
+            .into_iter()
+            .map(|text| WrappedLine {
+                columns: columns.take().unwrap_or_default(),
+                text,
+                comment: number,
+            })
+            .collect()
     }
 
-    pub fn comments(&self) -> &VecDeque<String> {
-        &self.comments
+    /// 保持する行数を超えた分を、古いコメントから丸ごと捨てる。
+    /// 最新のコメントは捨てず、それだけで超えるなら末尾の行だけ残す。
+    fn trim(&mut self) {
+        let cap = self.scrollback.max(self.height).max(1);
+        while self.lines.len() > cap && self.comments.len() > 1 {
+            self.comments.pop_front();
+            let number = self.dropped_comments;
+            self.dropped_comments += 1;
+            while self
+                .lines
+                .front()
+                .is_some_and(|line| line.comment == number)
+            {
+                self.lines.pop_front();
+                self.dropped += 1;
+            }
+        }
+        while self.lines.len() > cap {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
     }
 }
@@ -0,0 +1,43 @@
+use std::collections::{HashSet, VecDeque};
+
+/// 直近 `capacity` 件の `meta.id` を覚えておき、同じメッセージを 2 度通さないようにする
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    seen: HashSet<String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl Deduplicator {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            seen: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// 初めて見る ID なら記録して `true` を返す。空の ID は常に `true`。
+    pub fn insert(&mut self, id: &str) -> bool {
+        if id.is_empty() || self.capacity == 0 {
+            return true;
+        }
+        if self.seen.contains(id) {
+            return false;
+        }
+
+        if self.order.len() >= self.capacity
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.seen.remove(&oldest);
+        }
+        self.seen.insert(id.to_string());
+        self.order.push_back(id.to_string());
+        true
+    }
+
+    /// 覚えている ID (古い順)
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.order.iter().map(String::as_str)
+    }
+}
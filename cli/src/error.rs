@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use thiserror::Error;
+
+/// 既定の [`NdgrClient`](crate::client::NdgrClient) の組み立てに失敗していた。
+/// そのクライアントでのリクエストはすべてこのエラーになる。
+#[derive(Debug, Clone, Error)]
+#[error("failed to build the http client: {0}")]
+pub struct ClientBuildError(pub(crate) Arc<anyhow::Error>);
+
+/// [`fetch_program_info`](crate::fetch_program_info) の失敗
+#[derive(Debug, Error)]
+pub enum ProgramInfoError {
+    #[error(transparent)]
+    Client(#[from] ClientBuildError),
+    #[error("failed to fetch program page: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("program page returned HTTP {0}")]
+    HttpStatus(reqwest::StatusCode),
+    #[error("#embedded-data not found in program page")]
+    EmbeddedDataNotFound,
+    #[error("unexpected #embedded-data format: {0}")]
+    Schema(#[from] serde_json::Error),
+    #[error("program has ended")]
+    ProgramEnded,
+    #[error("program has not started yet")]
+    NotStarted,
+    #[error("login required to watch this program")]
+    LoginRequired,
+    #[error("community or channel membership required to watch this program")]
+    MembershipRequired,
+    #[error("program is not available in this region")]
+    RegionRestricted,
+    /// 上のどれにも当てはまらない視聴不可の理由 (`userProgramWatch.rejectedReasons`)
+    #[error("cannot watch this program: {}", .0.join(", "))]
+    Rejected(Vec<String>),
+}
+
+impl ProgramInfoError {
+    /// JS 側などで表示を切り替えるための識別子
+    pub fn code(&self) -> &'static str {
+        match self {
+            ProgramInfoError::Client(_) | ProgramInfoError::Request(_) => "request",
+            ProgramInfoError::HttpStatus(_) => "httpStatus",
+            ProgramInfoError::EmbeddedDataNotFound => "embeddedDataNotFound",
+            ProgramInfoError::Schema(_) => "schema",
+            ProgramInfoError::ProgramEnded => "programEnded",
+            ProgramInfoError::NotStarted => "notStarted",
+            ProgramInfoError::LoginRequired => "loginRequired",
+            ProgramInfoError::MembershipRequired => "membershipRequired",
+            ProgramInfoError::RegionRestricted => "regionRestricted",
+            ProgramInfoError::Rejected(_) => "rejected",
+        }
+    }
+}
+
+/// [`resolve_watch_url`](crate::resolve::resolve_watch_url) の失敗
+#[derive(Debug, Error)]
+pub enum ResolveError {
+    #[error("not a program, community or channel: {0}")]
+    InvalidInput(String),
+    /// コミュニティ・チャンネルが放送していない
+    #[error("{0} is not on air")]
+    NotOnAir(String),
+    #[error(transparent)]
+    ProgramInfo(#[from] ProgramInfoError),
+}
@@ -0,0 +1,104 @@
+use anyhow::Result;
+use futures::pin_mut;
+use futures_util::StreamExt;
+use prost::Message;
+use protobuf::chat::service::edge::chunked_entry::Entry;
+use protobuf::chat::service::edge::{ChunkedMessage, PackedSegment};
+
+use crate::ViewQuery;
+use crate::archive::PayloadKind;
+use crate::client::NdgrClient;
+
+/// 接続より前に投稿されたメッセージと、その時点の番組状態
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    /// 古い順に並んだ過去のメッセージ
+    pub messages: Vec<ChunkedMessage>,
+    /// 統計やアンケートなど、現在の状態のスナップショット
+    pub snapshot: Vec<ChunkedMessage>,
+}
+
+impl History {
+    /// 過去メッセージの `meta.id` ([`StreamOptions::seen_ids`](crate::StreamOptions::seen_ids) に渡す)
+    pub fn message_ids(&self) -> Vec<String> {
+        self.messages
+            .iter()
+            .filter_map(|message| message.meta.as_ref())
+            .map(|meta| meta.id.clone())
+            .collect()
+    }
+}
+
+/// 既定の [`NdgrClient`] で [`NdgrClient::fetch_history`] を呼ぶ
+pub async fn fetch_history(view_uri: &str, limit: usize) -> Result<History> {
+    NdgrClient::default().fetch_history(view_uri, limit).await
+}
+
+pub async fn fetch_packed_segment(url: &str) -> Result<PackedSegment> {
+    NdgrClient::default().fetch_packed_segment(url).await
+}
+
+impl NdgrClient {
+    /// view URI の `Backward` セグメントから `PackedSegment.next` をたどって、
+    /// 最新から最大 `limit` 件の過去メッセージと状態スナップショットを取得する。
+    pub async fn fetch_history(&self, view_uri: &str, limit: usize) -> Result<History> {
+        let mut backward = None;
+
+        let stream = self.fetch_chunked_entry(view_uri, &ViewQuery::Now).await;
+        pin_mut!(stream);
+
+        while let Some(entry) = stream.next().await {
+            if let Some(Entry::Backward(segment)) = entry?.entry {
+                backward = Some(segment);
+                break;
+            }
+        }
+
+        let Some(backward) = backward else {
+            return Ok(History::default());
+        };
+
+        let snapshot = match &backward.snapshot {
+            Some(snapshot) => self.fetch_snapshot(&snapshot.uri).await?,
+            None => Vec::new(),
+        };
+
+        // 新しいセグメントから順にたどるので、セグメント単位で後から逆順に並べ直す
+        let mut segments = Vec::new();
+        let mut count = 0;
+        let mut next_uri = backward.segment.map(|next| next.uri);
+
+        while let Some(uri) = next_uri.take() {
+            if count >= limit {
+                break;
+            }
+            let segment = self.fetch_packed_segment(&uri).await?;
+            count += segment.messages.len();
+            next_uri = segment.next.map(|next| next.uri);
+            segments.push(segment.messages);
+        }
+
+        let mut messages: Vec<ChunkedMessage> = segments.into_iter().rev().flatten().collect();
+        if messages.len() > limit {
+            messages.drain(..messages.len() - limit);
+        }
+
+        Ok(History { messages, snapshot })
+    }
+
+    pub async fn fetch_packed_segment(&self, url: &str) -> Result<PackedSegment> {
+        let bytes = self.fetch_bytes(url, PayloadKind::PackedSegment).await?;
+        Ok(PackedSegment::decode(bytes)?)
+    }
+
+    async fn fetch_snapshot(&self, url: &str) -> Result<Vec<ChunkedMessage>> {
+        let stream = self.fetch_chunked_message(url).await;
+        pin_mut!(stream);
+
+        let mut messages = Vec::new();
+        while let Some(message) = stream.next().await {
+            messages.push(message?);
+        }
+        Ok(messages)
+    }
+}
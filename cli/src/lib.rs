@@ -1,36 +1,83 @@
 use anyhow::Result;
-use async_stream::{stream, try_stream};
-use bytes::BytesMut;
+use async_stream::stream;
 use futures::pin_mut;
 use futures_core::stream::Stream;
 use futures_util::StreamExt;
+use protobuf::chat::data::program_status;
 use protobuf::chat::service::edge::chunked_entry::Entry;
+use protobuf::chat::service::edge::chunked_message::Payload;
 use protobuf::chat::service::edge::{ChunkedEntry, ChunkedMessage};
 
-use crate::program_info::ProgramInfo;
+use crate::client::NdgrClient;
+use crate::codec::DecodeError;
+use crate::dedup::Deduplicator;
+use crate::error::ProgramInfoError;
+use crate::program_info::{ProgramInfo, ProgramStatus};
+use crate::retry::{RetryConfig, StreamError};
 
+pub mod archive;
+pub mod client;
+pub mod codec;
 pub mod comment_buffer;
+pub mod dedup;
+pub mod error;
+pub mod history;
+pub mod model;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod multi;
 pub mod program_info;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod replay;
+pub mod resolve;
+pub mod resume;
+pub mod retry;
+pub mod state;
+mod time;
+pub mod timeshift;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod websocket;
 
-// TODO 番組終了の場合の処理
-pub async fn fetch_program_info(url: &str) -> Result<ProgramInfo> {
-    let html = reqwest::Client::new().get(url).send().await?.text().await?;
+/// 既定の [`NdgrClient`] で [`NdgrClient::fetch_program_info`] を呼ぶ
+pub async fn fetch_program_info(url: &str) -> Result<ProgramInfo, ProgramInfoError> {
+    NdgrClient::default().fetch_program_info(url).await
+}
 
-    let document = scraper::Html::parse_document(&html);
-    let selector = scraper::Selector::parse("#embedded-data").unwrap();
+pub(crate) fn check_watchable(info: &ProgramInfo) -> Result<(), ProgramInfoError> {
+    for reason in &info.user_program_watch.rejected_reasons {
+        let error = match reason.as_str() {
+            "notLogin" => ProgramInfoError::LoginRequired,
+            "notCommunityMember" | "notChannelMember" | "notSocialGroupMember" => {
+                ProgramInfoError::MembershipRequired
+            }
+            "programNotBegun" => ProgramInfoError::NotStarted,
+            "programEnded" | "timeshiftNotAvailable" | "timeshiftExpired" => {
+                ProgramInfoError::ProgramEnded
+            }
+            "notAllowedCountry" | "regionRestricted" => ProgramInfoError::RegionRestricted,
+            _ => continue,
+        };
+        return Err(error);
+    }
+    if !info.user_program_watch.rejected_reasons.is_empty() {
+        return Err(ProgramInfoError::Rejected(
+            info.user_program_watch.rejected_reasons.clone(),
+        ));
+    }
 
-    if let Some(element) = document.select(&selector).next() {
-        if let Some(data_props) = element.value().attr("data-props") {
-            let info: program_info::ProgramInfo = serde_json::from_str(data_props)?;
-            return Ok(info);
-        }
+    // 理由が書かれていなくても WebSocket URL がなければ視聴できない
+    if info.site.relive.web_socket_url.is_empty() {
+        return Err(match info.program.status {
+            ProgramStatus::Ended => ProgramInfoError::ProgramEnded,
+            ProgramStatus::Reserved => ProgramInfoError::NotStarted,
+            _ if !info.user.is_logged_in => ProgramInfoError::LoginRequired,
+            _ => ProgramInfoError::MembershipRequired,
+        });
     }
 
-    Err(anyhow::anyhow!("program info not found"))
+    Ok(())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ViewQuery {
     Now,
     At(i64),
@@ -40,68 +87,193 @@ pub async fn fetch_chunked_entry(
     url: &str,
     query: &ViewQuery,
 ) -> impl Stream<Item = Result<ChunkedEntry>> + use<> {
-    let at_str = match query {
-        ViewQuery::Now => "now".to_string(),
-        ViewQuery::At(at) => at.to_string(),
-    };
-    let url = format!("{url}?at={at_str}");
-
-    fetch_protobuf_stream::<ChunkedEntry>(&url).await
+    NdgrClient::default().fetch_chunked_entry(url, query).await
 }
 
 pub async fn fetch_chunked_message(
     url: &str,
 ) -> impl Stream<Item = Result<ChunkedMessage>> + use<> {
-    fetch_protobuf_stream::<ChunkedMessage>(url).await
+    NdgrClient::default().fetch_chunked_message(url).await
 }
 
 pub async fn fetch_protobuf_stream<T: prost::Message + Default>(
     url: &str,
 ) -> impl Stream<Item = Result<T>> + use<T> {
-    let response = reqwest::get(url).await;
+    NdgrClient::default().fetch_protobuf_stream::<T>(url).await
+}
 
-    try_stream! {
-        let mut stream = response?.bytes_stream();
-        let mut buffer = BytesMut::new();
+/// [`StreamOptions::dedup`] の既定値
+pub(crate) const DEFAULT_DEDUP_CAPACITY: usize = 10_000;
 
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            buffer.extend_from_slice(&chunk);
+/// [`stream_chunked_message_with_options`] の設定
+#[derive(Debug, Clone)]
+pub struct StreamOptions {
+    /// 最初に view URI に問い合わせる位置
+    pub start: ViewQuery,
+    pub retry: RetryConfig,
+    /// view URI とセグメント URI の前に連結する文字列 (CORS 回避用プロキシなど)
+    pub uri_prefix: String,
+    /// セグメントの重なりで同じメッセージが 2 度届くのを防ぐため、
+    /// 直近この件数の `meta.id` を覚えておく。`None` なら重複除去しない。
+    pub dedup: Option<usize>,
+    /// 重複除去に最初から覚えさせておく `meta.id`。
+    /// 直前に取った過去コメントを渡すと、最新のセグメントとの重なりを流さない。
+    pub seen_ids: Vec<String>,
+    /// view URI とセグメントの取得に使うクライアント
+    pub client: NdgrClient,
+}
 
-            while let Ok(message) = T::decode_length_delimited(&mut buffer) {
-                yield message;
-            }
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            start: ViewQuery::Now,
+            retry: RetryConfig::default(),
+            uri_prefix: String::new(),
+            dedup: Some(DEFAULT_DEDUP_CAPACITY),
+            seen_ids: Vec::new(),
+            client: NdgrClient::default(),
         }
     }
 }
 
-pub async fn stream_chunked_message<'a>(
-    view_uri: &'a str,
-) -> impl Stream<Item = ChunkedMessage> + 'a {
+/// メッセージストリームに流れてくるもの
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum StreamItem {
+    Message(ChunkedMessage),
+    /// 番組が終了した。ストリームはこの後に終わる。
+    ProgramEnded,
+}
+
+/// 既定の設定で [`stream_chunked_message_with_options`] を呼ぶ
+pub async fn stream_chunked_message(
+    view_uri: &str,
+) -> impl Stream<Item = Result<StreamItem, StreamError>> + use<> {
+    stream_chunked_message_with_options(view_uri, StreamOptions::default()).await
+}
+
+/// view URI をたどってメッセージを流し続ける。
+/// 中身の読めないフレームは `Err` (`attempt == 0`) を流して読み飛ばす。
+/// 取得に失敗したときは `Err` を流してから待機して再試行し、
+/// 再試行の上限に達したら最後の `Err` (`retrying == false`) を流して終わる。
+/// 番組終了の状態を受け取るか、view が次の位置 (`Next`) を返さなくなったら
+/// [`StreamItem::ProgramEnded`] を流して終わる。
+pub async fn stream_chunked_message_with_options(
+    view_uri: &str,
+    options: StreamOptions,
+) -> impl Stream<Item = Result<StreamItem, StreamError>> + use<> {
+    let view_uri = format!("{}{view_uri}", options.uri_prefix);
     stream! {
-        let mut view_query = ViewQuery::Now;
+        let mut view_query = options.start;
+        let mut attempt = 0;
+        let mut dedup = options.dedup.map(|capacity| {
+            let mut dedup = Deduplicator::new(capacity);
+            for id in &options.seen_ids {
+                dedup.insert(id);
+            }
+            dedup
+        });
         loop {
-            let stream = fetch_chunked_entry(view_uri, &view_query).await;
+            let mut error = None;
+            let mut got_next = false;
+
+            let stream = options.client.fetch_chunked_entry(&view_uri, &view_query).await;
             pin_mut!(stream);
 
-            while let Some(Ok(message)) = stream.next().await {
-                if let Some(entry) = message.entry {
-                    match entry {
-                        Entry::Next(next) => {
-                            view_query = ViewQuery::At(next.at);
-                        }
-                        Entry::Segment(segment) => {
-                            let stream = fetch_chunked_message(&segment.uri).await;
-                            pin_mut!(stream);
+            while let Some(entry) = stream.next().await {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) if is_recoverable(&e) => {
+                        yield Err(StreamError::skipped(e));
+                        continue;
+                    }
+                    Err(e) => {
+                        error = Some(e);
+                        break;
+                    }
+                };
+                match entry.entry {
+                    Some(Entry::Next(next)) => {
+                        view_query = ViewQuery::At(next.at);
+                        got_next = true;
+                    }
+                    Some(Entry::Segment(segment)) => {
+                        let segment_uri = format!("{}{}", options.uri_prefix, segment.uri);
+                        let stream = options.client.fetch_chunked_message(&segment_uri).await;
+                        pin_mut!(stream);
 
-                            while let Some(Ok(message)) = stream.next().await {
-                                yield message;
+                        while let Some(message) = stream.next().await {
+                            match message {
+                                Ok(message) => {
+                                    if let Some(dedup) = &mut dedup
+                                        && let Some(meta) = &message.meta
+                                        && !dedup.insert(&meta.id)
+                                    {
+                                        continue;
+                                    }
+                                    let ended = is_program_ended(&message);
+                                    yield Ok(StreamItem::Message(message));
+                                    if ended {
+                                        yield Ok(StreamItem::ProgramEnded);
+                                        return;
+                                    }
+                                }
+                                Err(e) if is_recoverable(&e) => {
+                                    yield Err(StreamError::skipped(e));
+                                }
+                                Err(e) => {
+                                    error = Some(e);
+                                    break;
+                                }
                             }
                         }
-                        _ => (),
+                        if error.is_some() {
+                            break;
+                        }
                     }
+                    _ => (),
                 }
             }
+
+            if error.is_none() && !got_next {
+                yield Ok(StreamItem::ProgramEnded);
+                return;
+            }
+
+            let Some(error) = error else {
+                attempt = 0;
+                continue;
+            };
+
+            attempt += 1;
+            let retrying = options.retry.should_retry(attempt);
+            yield Err(StreamError {
+                attempt,
+                retrying,
+                source: error,
+            });
+            if !retrying {
+                return;
+            }
+            time::sleep(options.retry.delay(attempt)).await;
         }
     }
 }
+
+/// 壊れたフレーム 1 つだけの失敗で、同じレスポンスを読み進められるかどうか
+fn is_recoverable(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<DecodeError>()
+        .is_some_and(DecodeError::is_recoverable)
+}
+
+pub(crate) fn is_program_ended(message: &ChunkedMessage) -> bool {
+    matches!(
+        &message.payload,
+        Some(Payload::State(state))
+            if state
+                .program_status
+                .as_ref()
+                .is_some_and(|status| status.state() == program_status::State::Ended)
+    )
+}
@@ -1,116 +1,96 @@
-use std::env;
-use std::io::{Write, stdout};
-use std::time::Duration;
+use std::path::PathBuf;
 
 use anyhow::Result;
-use crossterm::event::{self, DisableMouseCapture, Event as CEvent, KeyCode, KeyEvent};
-use crossterm::terminal::{
-    Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
-};
-use crossterm::{ExecutableCommand, cursor};
-use futures::{StreamExt, pin_mut};
-use ndgr_client::comment_buffer::CommentBuffer;
-use ndgr_client::websocket::WebSocketClient;
-use ndgr_client::{fetch_program_info, stream_chunked_message};
-use tokio::select;
-use tokio::sync::mpsc;
+use clap::{Args, Parser, Subcommand};
+use ndgr_client::client::{ClientConfig, NdgrClient};
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let url = env::args().nth(1).expect("URL is required");
-
-    let info = fetch_program_info(&url).await.unwrap();
-
-    let web_socket_client = WebSocketClient::new(&info.site.relive.web_socket_url)
-        .await
-        .unwrap();
-    let view_uri = web_socket_client.view_uri();
-
-    println!("view_uri: {}", view_uri);
-
-    let stream = stream_chunked_message(view_uri).await;
-    pin_mut!(stream);
+use crate::commands::OutputFormat;
 
-    enable_raw_mode()?;
-    let mut stdout = stdout();
+mod commands;
 
-    stdout.execute(EnterAlternateScreen)?;
-    stdout.execute(Clear(ClearType::All))?;
-    stdout.flush()?;
-
-    let (width, height) = crossterm::terminal::size()?;
-
-    let mut comment_buffer = CommentBuffer::new(width as usize, height as usize - 2);
+/// ニコニコ生放送のコメントを NDGR サーバーから受信する
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(flatten)]
+    global: GlobalArgs,
+    #[command(subcommand)]
+    command: Command,
+}
 
-    let (tx, mut rx) = mpsc::unbounded_channel();
+#[derive(Debug, Args)]
+struct GlobalArgs {
+    /// ログインセッションの user_session Cookie の値
+    #[arg(
+        long,
+        global = true,
+        env = "NICONICO_USER_SESSION",
+        hide_env_values = true
+    )]
+    cookie: Option<String>,
+    /// Cookie ファイル (Netscape 形式の cookies.txt か、user_session の値だけを書いたファイル)
+    #[arg(long, global = true)]
+    cookie_file: Option<PathBuf>,
+    /// プロキシの URL (http:// / socks5:// / socks5h://)
+    #[arg(long, global = true)]
+    proxy: Option<String>,
+    /// User-Agent ヘッダー (既定は ndgr-client/<バージョン>)
+    #[arg(long, global = true)]
+    user_agent: Option<String>,
+    /// dump / replay / info の出力形式
+    #[arg(long, global = true, value_enum, default_value_t)]
+    format: OutputFormat,
+}
 
-    tokio::spawn(async move {
-        loop {
-            if event::poll(Duration::from_millis(100)).unwrap() {
-                if let CEvent::Key(KeyEvent { code, .. }) = event::read().unwrap() {
-                    if tx.send(code).is_err() {
-                        break;
-                    }
-                }
-            }
+impl GlobalArgs {
+    fn build_client(&self) -> Result<NdgrClient> {
+        let mut config = ClientConfig {
+            proxy: self.proxy.clone(),
+            ..ClientConfig::default()
+        };
+        if let Some(user_agent) = &self.user_agent {
+            config.user_agent = user_agent.clone();
         }
-    });
-
-    let mut input = String::new();
 
-    loop {
-        stdout.execute(cursor::Hide)?;
-        stdout.execute(cursor::MoveTo(0, 0))?;
-
-        for (i, comment) in comment_buffer.comments().iter().enumerate() {
-            stdout.execute(cursor::MoveTo(0, i as u16)).unwrap();
-            write!(stdout, "{}", comment).unwrap();
-            stdout.execute(Clear(ClearType::UntilNewLine))?;
+        let mut builder = NdgrClient::builder().config(config);
+        if let Some(path) = &self.cookie_file {
+            builder = builder.cookie_file(path)?;
         }
-
-        stdout.execute(cursor::MoveTo(0, height - 1))?;
-        stdout.execute(Clear(ClearType::CurrentLine))?;
-        print!("コメント入力: {}", input);
-        stdout.flush()?;
-
-        select! {
-            message = stream.next() => {
-                if let Some(message) = message {
-                    let msg_str = format!("{:?}", message);
-                    comment_buffer.push(msg_str);
-                } else {
-                    break;
-                }
-            },
-            Some(key) = rx.recv() => {
-                match key {
-                    KeyCode::Char(c) => {
-                        input.push(c);
-                    },
-                    KeyCode::Backspace => {
-                        input.pop();
-                    },
-                    KeyCode::Enter => {
-                        if !input.is_empty() {
-                            // TODO
-                            comment_buffer.push(format!("あなた: {}", input));
-                            input.clear();
-                        }
-                    },
-                    KeyCode::Esc => {
-                        break;
-                    },
-                    _ => {}
-                }
-            },
+        if let Some(cookie) = &self.cookie {
+            builder = builder.user_session(cookie);
         }
-
-        stdout.execute(cursor::Show)?;
+        builder.build()
     }
+}
 
-    disable_raw_mode()?;
-    stdout.execute(LeaveAlternateScreen)?;
-    stdout.execute(DisableMouseCapture)?;
-    stdout.execute(cursor::Show)?;
-    Ok(())
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// TUI でコメントを見ながら投稿する
+    Watch(commands::watch::WatchArgs),
+    /// 複数の番組のコメントを同時に TUI で見る
+    Multi(commands::multi::MultiArgs),
+    /// 受信したメッセージを標準出力に流す
+    Dump(commands::dump::DumpArgs),
+    /// 受信した生のメッセージをファイルに保存する
+    Record(commands::record::RecordArgs),
+    /// 保存したメッセージを再生する
+    Replay(commands::replay::ReplayArgs),
+    /// 番組情報を表示する
+    Info(commands::info::InfoArgs),
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let client = cli.global.build_client()?;
+    let format = cli.global.format;
+
+    match cli.command {
+        Command::Watch(args) => commands::watch::run(&client, args).await,
+        Command::Multi(args) => commands::multi::run(&client, args).await,
+        Command::Dump(args) => commands::dump::run(&client, args, format).await,
+        Command::Record(args) => commands::record::run(&client, args).await,
+        Command::Replay(args) => commands::replay::run(args, format).await,
+        Command::Info(args) => commands::info::run(&client, args, format).await,
+    }
 }
@@ -0,0 +1,469 @@
+use std::fmt;
+
+use protobuf::chat::data::nicolive_message::Data;
+use protobuf::chat::data::{
+    NicoliveState, comment_lock, comment_mode, enquete, marquee, move_order, nicoad,
+    program_status, simple_notification, trial_panel,
+};
+use protobuf::chat::service::edge::ChunkedMessage;
+use protobuf::chat::service::edge::chunked_message::{self, Payload};
+use serde::Serialize;
+
+/// `ChunkedMessage` を変換した所有型のメッセージ。
+/// JSON にすると `{"id": …, "at": …, "type": "chat", …}` のようなフラットな形になる。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub id: String,
+    /// 投稿時刻 (UNIX 秒)
+    pub at: Option<i64>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+impl Message {
+    /// JSON にしたときに現れうるキー。どれが出るかは `type` によって異なる。
+    pub const FIELDS: &[&str] = &[
+        "id",
+        "at",
+        "type",
+        // chat
+        "no",
+        "vpos",
+        "content",
+        "name",
+        "rawUserId",
+        "hashedUserId",
+        "premium",
+        "overflowed",
+        // gift
+        "itemId",
+        "advertiserUserId",
+        "advertiserName",
+        "itemName",
+        "point",
+        "message",
+        // notification
+        "kind",
+        // stateChange
+        "statistics",
+        "enquete",
+        "moveOrder",
+        "marquee",
+        "commentLocked",
+        "commentMode",
+        "trialPanel",
+        "programStatus",
+        // signal
+        "signal",
+    ];
+
+    /// 表示対象外のメッセージ (ゲーム更新、モデレーター操作など) は `None` を返す。
+    pub fn from_chunked_message(message: &ChunkedMessage) -> Option<Self> {
+        let meta = message.meta.as_ref();
+        let id = meta.map(|meta| meta.id.clone()).unwrap_or_default();
+        let at = meta.and_then(|meta| meta.at.as_ref()).map(|at| at.seconds);
+
+        let event = Event::from_payload(message.payload.as_ref()?)?;
+
+        Some(Self { id, at, event })
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.event.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    Chat(Chat),
+    Gift(Gift),
+    Nicoad(Nicoad),
+    Notification(Notification),
+    StateChange(StateChange),
+    Signal(Signal),
+}
+
+impl Event {
+    fn from_payload(payload: &Payload) -> Option<Self> {
+        match payload {
+            Payload::Message(message) => match message.data.as_ref()? {
+                Data::Chat(chat) => Some(Event::Chat(Chat::new(chat, false))),
+                Data::OverflowedChat(chat) => Some(Event::Chat(Chat::new(chat, true))),
+                Data::SimpleNotification(notification) => {
+                    Notification::new(notification).map(Event::Notification)
+                }
+                Data::Gift(gift) => Some(Event::Gift(gift.into())),
+                Data::Nicoad(ad) => Nicoad::new(ad).map(Event::Nicoad),
+                _ => None,
+            },
+            Payload::State(state) => Some(Event::StateChange(state.into())),
+            Payload::Signal(signal) => match chunked_message::Signal::try_from(*signal).ok()? {
+                chunked_message::Signal::Flushed => Some(Event::Signal(Signal::Flushed)),
+            },
+        }
+    }
+
+    /// チャット・ギフト・ニコニ広告・通知のように、コメント欄に流す種類かどうか
+    pub fn is_comment(&self) -> bool {
+        matches!(
+            self,
+            Event::Chat(_) | Event::Gift(_) | Event::Nicoad(_) | Event::Notification(_)
+        )
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Chat(chat) => {
+                write!(f, "{} {}: {}", chat.no, chat.user_label(), chat.content)
+            }
+            Event::Gift(gift) => write!(
+                f,
+                "🎁 {} さんが「{}」を贈りました ({}pt)",
+                gift.advertiser_name, gift.item_name, gift.point
+            ),
+            Event::Nicoad(ad) => write!(f, "📣 {}", ad.content),
+            Event::Notification(notification) => f.write_str(&notification.content),
+            Event::StateChange(state) => state.fmt(f),
+            Event::Signal(Signal::Flushed) => f.write_str("[flushed]"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chat {
+    pub no: i32,
+    pub vpos: i32,
+    pub content: String,
+    pub name: Option<String>,
+    pub raw_user_id: Option<i64>,
+    pub hashed_user_id: Option<String>,
+    pub premium: bool,
+    /// 混雑時に間引かれた側 (`overflowed_chat`) のコメントかどうか
+    pub overflowed: bool,
+}
+
+impl Chat {
+    fn new(chat: &protobuf::chat::data::Chat, overflowed: bool) -> Self {
+        Self {
+            no: chat.no,
+            vpos: chat.vpos,
+            content: chat.content.clone(),
+            name: chat.name.clone(),
+            raw_user_id: chat.raw_user_id,
+            hashed_user_id: chat.hashed_user_id.clone(),
+            premium: chat.account_status == 1,
+            overflowed,
+        }
+    }
+
+    /// 名前 → 生 ID → ハッシュ化 ID の順で表示用のユーザー名を決める
+    pub fn user_label(&self) -> String {
+        if let Some(name) = &self.name {
+            name.clone()
+        } else if let Some(raw_user_id) = self.raw_user_id {
+            raw_user_id.to_string()
+        } else {
+            self.hashed_user_id.clone().unwrap_or_default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Gift {
+    pub item_id: String,
+    pub advertiser_user_id: Option<i64>,
+    pub advertiser_name: String,
+    pub item_name: String,
+    pub point: i64,
+    pub message: String,
+}
+
+impl From<&protobuf::chat::data::Gift> for Gift {
+    fn from(gift: &protobuf::chat::data::Gift) -> Self {
+        Self {
+            item_id: gift.item_id.clone(),
+            advertiser_user_id: gift.advertiser_user_id,
+            advertiser_name: gift.advertiser_name.clone(),
+            item_name: gift.item_name.clone(),
+            point: gift.point,
+            message: gift.message.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Nicoad {
+    pub content: String,
+}
+
+impl Nicoad {
+    fn new(ad: &protobuf::chat::data::Nicoad) -> Option<Self> {
+        let content = match ad.versions.as_ref()? {
+            nicoad::Versions::V0(v0) => v0
+                .latest
+                .as_ref()
+                .map(|latest| format!("{} {}pt", latest.advertiser, latest.point))
+                .unwrap_or_default(),
+            nicoad::Versions::V1(v1) => v1.message.clone(),
+        };
+        Some(Self { content })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub content: String,
+}
+
+impl Notification {
+    fn new(notification: &protobuf::chat::data::SimpleNotification) -> Option<Self> {
+        use simple_notification::Message;
+        let (kind, content) = match notification.message.as_ref()? {
+            Message::Ichiba(s) => (NotificationKind::Ichiba, s),
+            Message::Quote(s) => (NotificationKind::Quote, s),
+            Message::Emotion(s) => (NotificationKind::Emotion, s),
+            Message::Cruise(s) => (NotificationKind::Cruise, s),
+            Message::ProgramExtended(s) => (NotificationKind::ProgramExtended, s),
+            Message::RankingIn(s) => (NotificationKind::RankingIn, s),
+            Message::RankingUpdated(s) => (NotificationKind::RankingUpdated, s),
+            Message::Visited(s) => (NotificationKind::Visited, s),
+        };
+        Some(Self {
+            kind,
+            content: content.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationKind {
+    Ichiba,
+    Quote,
+    Emotion,
+    Cruise,
+    ProgramExtended,
+    RankingIn,
+    RankingUpdated,
+    Visited,
+}
+
+/// `NicoliveState` の差分。送られてきた項目だけが `Some` になる。
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateChange {
+    pub statistics: Option<Statistics>,
+    pub enquete: Option<Enquete>,
+    pub move_order: Option<MoveOrder>,
+    pub marquee: Option<Marquee>,
+    pub comment_locked: Option<bool>,
+    pub comment_mode: Option<CommentMode>,
+    pub trial_panel: Option<TrialPanel>,
+    pub program_status: Option<ProgramStatus>,
+}
+
+impl From<&NicoliveState> for StateChange {
+    fn from(state: &NicoliveState) -> Self {
+        Self {
+            statistics: state.statistics.as_ref().map(|statistics| Statistics {
+                viewers: statistics.viewers,
+                comments: statistics.comments,
+                ad_points: statistics.ad_points,
+                gift_points: statistics.gift_points,
+            }),
+            enquete: state.enquete.as_ref().map(|enquete| Enquete {
+                question: enquete.question.clone(),
+                choices: enquete
+                    .choices
+                    .iter()
+                    .map(|choice| EnqueteChoice {
+                        description: choice.description.clone(),
+                        per_mille: choice.per_mille,
+                    })
+                    .collect(),
+                status: match enquete.status() {
+                    enquete::Status::Poll => EnqueteStatus::Poll,
+                    enquete::Status::Result => EnqueteStatus::Result,
+                    enquete::Status::Closed => EnqueteStatus::Closed,
+                },
+            }),
+            move_order: state.move_order.as_ref().and_then(|move_order| {
+                match move_order.destination.as_ref()? {
+                    move_order::Destination::Jump(jump) => Some(MoveOrder::Jump {
+                        content: jump.content.to_string(),
+                        message: jump.message.clone(),
+                    }),
+                    move_order::Destination::Redirect(redirect) => Some(MoveOrder::Redirect {
+                        uri: redirect.uri.clone(),
+                        message: redirect.message.clone(),
+                    }),
+                }
+            }),
+            marquee: state.marquee.as_ref().map(|marquee| Marquee {
+                operator_comment: marquee
+                    .display
+                    .as_ref()
+                    .and_then(|display| display.operator_comment.as_ref())
+                    .map(OperatorComment::from),
+            }),
+            comment_locked: state
+                .comment_lock
+                .as_ref()
+                .map(|lock| lock.status() == comment_lock::Status::Locked),
+            comment_mode: state.comment_mode.as_ref().map(|mode| match mode.layout() {
+                comment_mode::Layout::Normal => CommentMode::Normal,
+                comment_mode::Layout::Background => CommentMode::Background,
+            }),
+            trial_panel: state.trial_panel.as_ref().map(|panel| TrialPanel {
+                displayed: panel.panel() == trial_panel::Panel::Display,
+                unqualified_user: panel.unqualified_user,
+            }),
+            program_status: state
+                .program_status
+                .as_ref()
+                .map(|status| match status.state() {
+                    program_status::State::Ended => ProgramStatus::Ended,
+                    program_status::State::Unknown => ProgramStatus::Unknown,
+                }),
+        }
+    }
+}
+
+impl fmt::Display for StateChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(statistics) = &self.statistics {
+            parts.push(format!(
+                "来場者 {} / コメント {}",
+                statistics.viewers.unwrap_or_default(),
+                statistics.comments.unwrap_or_default()
+            ));
+        }
+        if let Some(enquete) = &self.enquete {
+            parts.push(format!("アンケート: {}", enquete.question));
+        }
+        if let Some(Marquee {
+            operator_comment: Some(comment),
+        }) = &self.marquee
+        {
+            parts.push(format!("運営コメント: {}", comment.content));
+        }
+        if let Some(locked) = self.comment_locked {
+            parts.push(
+                if locked {
+                    "コメント禁止"
+                } else {
+                    "コメント許可"
+                }
+                .to_string(),
+            );
+        }
+        if let Some(ProgramStatus::Ended) = self.program_status {
+            parts.push("番組終了".to_string());
+        }
+        write!(f, "[状態] {}", parts.join(" / "))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Statistics {
+    pub viewers: Option<i64>,
+    pub comments: Option<i64>,
+    pub ad_points: Option<i64>,
+    pub gift_points: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Enquete {
+    pub question: String,
+    pub choices: Vec<EnqueteChoice>,
+    pub status: EnqueteStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnqueteChoice {
+    pub description: String,
+    /// 結果発表時の得票率 (‰)
+    pub per_mille: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EnqueteStatus {
+    Poll,
+    Result,
+    Closed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum MoveOrder {
+    Jump { content: String, message: String },
+    Redirect { uri: String, message: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Marquee {
+    /// `None` なら表示中の運営コメントが消されたことを表す
+    pub operator_comment: Option<OperatorComment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorComment {
+    pub content: String,
+    pub name: Option<String>,
+    pub link: Option<String>,
+}
+
+impl From<&marquee::display::OperatorComment> for OperatorComment {
+    fn from(comment: &marquee::display::OperatorComment) -> Self {
+        Self {
+            content: comment.content.clone(),
+            name: comment.name.clone(),
+            link: comment.link.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CommentMode {
+    Normal,
+    Background,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrialPanel {
+    pub displayed: bool,
+    pub unqualified_user: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProgramStatus {
+    Unknown,
+    Ended,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "signal", rename_all = "camelCase")]
+pub enum Signal {
+    Flushed,
+}
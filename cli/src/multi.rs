@@ -0,0 +1,224 @@
+//! 複数の番組を 1 つのプロセスで同時に受信する
+
+use anyhow::Result;
+use futures::StreamExt;
+use futures_core::stream::Stream;
+use protobuf::chat::service::edge::ChunkedMessage;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::client::NdgrClient;
+use crate::program_info::ProgramInfo;
+use crate::resume::StreamResume;
+use crate::retry::StreamError;
+use crate::websocket::{CommentPoster, WebSocketClient, WebSocketEvent};
+use crate::{StreamItem, StreamOptions, stream_chunked_message_with_options};
+
+/// どの番組で起きたかを添えた [`WatchEvent`]
+#[derive(Debug)]
+pub struct ProgramEvent {
+    /// `lv` から始まる番組 ID
+    pub program_id: String,
+    pub event: WatchEvent,
+}
+
+/// 1 番組のセッションで起きたこと
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum WatchEvent {
+    Message(ChunkedMessage),
+    /// 番組が終了した。WebSocket のイベントはこの後も届くことがある。
+    ProgramEnded,
+    StreamError(StreamError),
+    WebSocket(WebSocketEvent),
+}
+
+/// 番組ごとに [`WebSocketClient`] とメッセージストリームを持ち、
+/// すべての番組のイベントを 1 本にまとめて [`MultiWatcher::next_event`] で返す。
+/// drop するとすべてのセッションを閉じる。
+pub struct MultiWatcher {
+    client: NdgrClient,
+    sessions: Vec<ProgramSession>,
+    tx: mpsc::UnboundedSender<ProgramEvent>,
+    rx: mpsc::UnboundedReceiver<ProgramEvent>,
+}
+
+struct ProgramSession {
+    info: ProgramInfo,
+    web_socket_client: WebSocketClient,
+    task: JoinHandle<()>,
+}
+
+impl MultiWatcher {
+    /// 番組ページ・WebSocket・セグメントの取得に `client` を使う
+    pub fn new(client: NdgrClient) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            client,
+            sessions: Vec::new(),
+            tx,
+            rx,
+        }
+    }
+
+    /// 番組ページ `watch_url` の番組を受信し始める。すでに受信中の番組ならエラー
+    pub async fn add(&mut self, watch_url: &str) -> Result<&ProgramInfo> {
+        let info = self.client.fetch_program_info(watch_url).await?;
+        let program_id = info.program.nicolive_program_id.clone();
+        if self.info(&program_id).is_some() {
+            anyhow::bail!("already watching {program_id}");
+        }
+
+        let web_socket_client =
+            WebSocketClient::connect(&self.client, &info.site.relive.web_socket_url).await?;
+        let task = tokio::spawn(forward_events(
+            program_id,
+            self.client.clone(),
+            &web_socket_client,
+            self.tx.clone(),
+        ));
+
+        self.sessions.push(ProgramSession {
+            info,
+            web_socket_client,
+            task,
+        });
+        Ok(&self.sessions.last().unwrap().info)
+    }
+
+    /// 受信をやめる。受信していない番組なら `false`
+    pub async fn remove(&mut self, program_id: &str) -> bool {
+        let Some(index) = self.position(program_id) else {
+            return false;
+        };
+        let session = self.sessions.remove(index);
+        session.task.abort();
+        session.web_socket_client.shutdown().await;
+        true
+    }
+
+    /// 追加した順の番組情報
+    pub fn programs(&self) -> impl Iterator<Item = &ProgramInfo> {
+        self.sessions.iter().map(|session| &session.info)
+    }
+
+    pub fn info(&self, program_id: &str) -> Option<&ProgramInfo> {
+        let index = self.position(program_id)?;
+        Some(&self.sessions[index].info)
+    }
+
+    /// `program_id` の番組にコメントを投稿するためのハンドル
+    pub fn poster(&self, program_id: &str) -> Option<CommentPoster> {
+        let index = self.position(program_id)?;
+        Some(self.sessions[index].web_socket_client.poster())
+    }
+
+    /// いずれかの番組で次に起きたイベント。番組がなければ追加されるまで待ち続ける。
+    pub async fn next_event(&mut self) -> ProgramEvent {
+        // 送信側を自分でも持っているので閉じることはない
+        self.rx.recv().await.expect("event channel closed")
+    }
+
+    /// すべてのセッションを閉じ、終わるまで待つ
+    pub async fn shutdown(mut self) {
+        for session in self.sessions.drain(..) {
+            session.task.abort();
+            session.web_socket_client.shutdown().await;
+        }
+    }
+
+    fn position(&self, program_id: &str) -> Option<usize> {
+        self.sessions
+            .iter()
+            .position(|session| session.info.program.nicolive_program_id == program_id)
+    }
+}
+
+impl Drop for MultiWatcher {
+    fn drop(&mut self) {
+        for session in &self.sessions {
+            session.task.abort();
+        }
+    }
+}
+
+/// 1 番組分のメッセージと WebSocket のイベントを `tx` に流す。
+/// 再接続でメッセージサーバーが変わったら、最後に受け取った時刻からストリームを張り直す。
+fn forward_events(
+    program_id: String,
+    client: NdgrClient,
+    web_socket_client: &WebSocketClient,
+    tx: mpsc::UnboundedSender<ProgramEvent>,
+) -> impl Future<Output = ()> + Send + use<> {
+    let mut view_uri_rx = web_socket_client.subscribe_view_uri();
+    let mut events = web_socket_client.subscribe_events();
+
+    async move {
+        let send = |event| {
+            tx.send(ProgramEvent {
+                program_id: program_id.clone(),
+                event,
+            })
+            .is_ok()
+        };
+
+        let mut resume = StreamResume::new();
+        let view_uri = view_uri_rx.borrow_and_update().clone();
+        let mut stream = Box::pin(open_stream(&client, &view_uri, &resume).await);
+        let mut stream_finished = false;
+        let mut web_socket_closed = false;
+
+        while !(stream_finished && web_socket_closed) {
+            let event = select! {
+                item = stream.next(), if !stream_finished => match item {
+                    Some(Ok(StreamItem::Message(message))) => {
+                        resume.record(&message);
+                        WatchEvent::Message(message)
+                    }
+                    Some(Ok(StreamItem::ProgramEnded)) => WatchEvent::ProgramEnded,
+                    Some(Err(e)) => WatchEvent::StreamError(e),
+                    None => {
+                        stream_finished = true;
+                        continue;
+                    }
+                },
+                Ok(()) = view_uri_rx.changed() => {
+                    let view_uri = view_uri_rx.borrow_and_update().clone();
+                    stream = Box::pin(open_stream(&client, &view_uri, &resume).await);
+                    stream_finished = false;
+                    continue;
+                }
+                event = events.recv(), if !web_socket_closed => match event {
+                    Ok(WebSocketEvent::Closed) => {
+                        web_socket_closed = true;
+                        WatchEvent::WebSocket(WebSocketEvent::Closed)
+                    }
+                    Ok(event) => WatchEvent::WebSocket(event),
+                    Err(RecvError::Closed) => {
+                        web_socket_closed = true;
+                        continue;
+                    }
+                    // 取りこぼしたイベントは諦める
+                    Err(RecvError::Lagged(_)) => continue,
+                },
+            };
+            if !send(event) {
+                return;
+            }
+        }
+    }
+}
+
+async fn open_stream(
+    client: &NdgrClient,
+    view_uri: &str,
+    resume: &StreamResume,
+) -> impl Stream<Item = Result<StreamItem, StreamError>> + Send + use<> {
+    let options = StreamOptions {
+        client: client.clone(),
+        ..StreamOptions::default()
+    };
+    stream_chunked_message_with_options(view_uri, resume.resume(options)).await
+}
@@ -1,17 +1,158 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Debug, Deserialize)]
+/// 番組ページの `#embedded-data` の `data-props`。
+/// 番組の種類や状態によって欠ける項目が多いので、ほとんどの項目は省略可能にしている。
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ProgramInfo {
     pub site: Site,
+    #[serde(default)]
+    pub program: Program,
+    /// 番組が属するコミュニティ・チャンネル
+    #[serde(default)]
+    pub social_group: SocialGroup,
+    /// 番組ページを取得したユーザー
+    #[serde(default)]
+    pub user: User,
+    #[serde(default)]
+    pub user_program_watch: UserProgramWatch,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Site {
     pub relive: Relive,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Relive {
+    /// 未放送の番組などでは空になる
+    #[serde(default)]
     pub web_socket_url: String,
 }
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Program {
+    /// `lv` から始まる番組 ID
+    pub nicolive_program_id: String,
+    pub title: String,
+    pub description: String,
+    pub status: ProgramStatus,
+    pub provider_type: ProviderType,
+    /// 開場時刻 (UNIX 秒)
+    pub open_time: i64,
+    /// 番組開始時刻 (UNIX 秒)
+    pub begin_time: i64,
+    /// コメントの vpos の基準時刻 (UNIX 秒)
+    pub vpos_base_time: i64,
+    /// 番組終了時刻 (UNIX 秒)
+    pub end_time: i64,
+    /// 延長前の終了予定時刻 (UNIX 秒)
+    pub scheduled_end_time: i64,
+    /// 放送者
+    pub supplier: Supplier,
+    pub tag: Tags,
+    pub thumbnail: Thumbnail,
+    pub watch_page_url: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ProgramStatus {
+    OnAir,
+    Ended,
+    /// 放送予約中 (未放送)
+    #[serde(rename = "RELEASED")]
+    Reserved,
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProviderType {
+    Community,
+    Channel,
+    Official,
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Supplier {
+    pub name: String,
+    /// 放送者のユーザー ID (チャンネル番組ではチャンネル ID)
+    #[serde(deserialize_with = "string_or_number")]
+    pub program_provider_id: String,
+    pub page_url: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Tags {
+    pub list: Vec<Tag>,
+    /// タグ編集がロックされているか
+    pub is_locked: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Tag {
+    pub text: String,
+    pub is_locked: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Thumbnail {
+    pub small: Option<String>,
+    pub large: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SocialGroup {
+    /// `co` / `ch` から始まる ID
+    pub id: String,
+    pub name: String,
+    pub r#type: String,
+    pub thumbnail_image_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct User {
+    pub is_logged_in: bool,
+    /// ログインしていなければ `None`
+    #[serde(deserialize_with = "optional_string_or_number")]
+    pub id: Option<String>,
+    pub nickname: Option<String>,
+    /// `premium` / `standard` など
+    pub account_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UserProgramWatch {
+    /// 視聴できない理由 (`notLogin` など)。視聴できるなら空
+    pub rejected_reasons: Vec<String>,
+}
+
+/// ID は文字列で来ることも数値で来ることもあるので、どちらも文字列として受け取る
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(optional_string_or_number(deserializer)?.unwrap_or_default())
+}
+
+fn optional_string_or_number<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(s) => Some(s),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    })
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_stream::stream;
+use futures_core::stream::Stream;
+use prost::Message;
+use protobuf::chat::service::edge::{ChunkedMessage, PackedSegment};
+use tokio::sync::mpsc;
+
+use crate::archive::{ArchiveReader, PayloadKind, RecordKind};
+use crate::dedup::Deduplicator;
+use crate::retry::StreamError;
+use crate::{DEFAULT_DEDUP_CAPACITY, StreamItem, is_program_ended, time};
+
+/// 読み込んだレコードを先読みしておく数
+const RECORD_BUFFER: usize = 64;
+
+/// 再生の間隔
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pacing {
+    /// 記録したときと同じ間隔で流す
+    RealTime,
+    /// 記録したときの間隔をこの倍率で縮めて流す。0 より大きくなければならない
+    Speed(f64),
+    /// 待たずに流す
+    AsFastAsPossible,
+}
+
+/// [`open_with_options`] の設定
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    pub pacing: Pacing,
+    /// [`StreamOptions::dedup`](crate::StreamOptions::dedup) と同じ
+    pub dedup: Option<usize>,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            pacing: Pacing::RealTime,
+            dedup: Some(DEFAULT_DEDUP_CAPACITY),
+        }
+    }
+}
+
+/// 既定の設定で [`open_with_options`] を呼ぶ
+pub async fn open(
+    path: &Path,
+) -> Result<impl Stream<Item = Result<StreamItem, StreamError>> + use<>> {
+    open_with_options(path, ReplayOptions::default()).await
+}
+
+/// [`record`](crate::archive) したアーカイブのメッセージを
+/// [`stream_chunked_message`](crate::stream_chunked_message) と同じ形で流す。
+/// 流すのはセグメントとスナップショットの `ChunkedMessage` と、過去ログの `PackedSegment` の中身。
+/// 過去ログは古い順に並べ直し、その後に記録したセグメントより先に流す。
+/// 最後まで流すか番組終了の状態に達したら [`StreamItem::ProgramEnded`] を流して終わる。
+/// アーカイブが壊れていたら `Err` (`retrying == false`) を流して終わる。
+/// `Pacing::Speed` の倍率が 0 以下か NaN ならエラーを返す。
+pub async fn open_with_options(
+    path: &Path,
+    options: ReplayOptions,
+) -> Result<impl Stream<Item = Result<StreamItem, StreamError>> + use<>> {
+    if let Pacing::Speed(speed) = options.pacing
+        && (speed.is_nan() || speed <= 0.0)
+    {
+        anyhow::bail!("replay speed must be positive: {speed}");
+    }
+
+    // ファイルの読み込みと zstd の展開はブロッキングなので、専用のスレッドで行って渡す
+    let path = path.to_path_buf();
+    let reader = tokio::task::spawn_blocking(move || ArchiveReader::open(path)).await??;
+    let (tx, mut records) = mpsc::channel(RECORD_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        for record in reader {
+            let failed = record.is_err();
+            // 受け取る側がいなくなったか、壊れたところまで読んだら終わる
+            if tx.blocking_send(record).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    Ok(stream! {
+        let mut dedup = options.dedup.map(Deduplicator::new);
+        let mut fetch_kinds = HashMap::new();
+        // 過去ログの `PackedSegment` は新しい順に取得しているので、溜めておいて古い順に流す
+        let mut history: Vec<(i64, Vec<ChunkedMessage>)> = Vec::new();
+        let mut first_received_at_ms = None;
+        let started_at = Instant::now();
+
+        loop {
+            let record = match records.recv().await {
+                Some(Ok(record)) => Some(record),
+                None => None,
+                Some(Err(source)) => {
+                    yield Err(StreamError { attempt: 1, retrying: false, source });
+                    return;
+                }
+            };
+
+            let mut live = None;
+            if let Some(record) = &record {
+                match &record.kind {
+                    Some(RecordKind::Fetch(fetch)) => {
+                        fetch_kinds.insert(fetch.id, fetch.kind());
+                        continue;
+                    }
+                    Some(RecordKind::Frame(frame)) => {
+                        let kind = fetch_kinds.get(&frame.fetch_id).copied();
+                        match decode_frame(kind, &frame.data) {
+                            Ok(Some(Payload::History(messages))) => {
+                                history.push((record.received_at_ms, messages));
+                                continue;
+                            }
+                            Ok(Some(Payload::Message(message))) => {
+                                live = Some((record.received_at_ms, message));
+                            }
+                            Ok(None) => continue,
+                            Err(source) => {
+                                yield Err(StreamError { attempt: 1, retrying: false, source });
+                                return;
+                            }
+                        }
+                    }
+                    None => continue,
+                }
+            }
+
+            // 過去ログは、続くセグメントのメッセージより先に流す
+            let messages = history
+                .drain(..)
+                .rev()
+                .flat_map(|(received_at_ms, messages)| {
+                    messages.into_iter().map(move |message| (received_at_ms, message))
+                })
+                .chain(live);
+            for (received_at_ms, message) in messages.collect::<Vec<_>>() {
+                if let Some(dedup) = &mut dedup
+                    && let Some(meta) = &message.meta
+                    && !dedup.insert(&meta.id)
+                {
+                    continue;
+                }
+
+                let speed = match options.pacing {
+                    Pacing::RealTime => Some(1.0),
+                    Pacing::Speed(speed) => Some(speed),
+                    Pacing::AsFastAsPossible => None,
+                };
+                if let Some(speed) = speed {
+                    let first = *first_received_at_ms.get_or_insert(received_at_ms);
+                    let offset_ms = (received_at_ms - first).max(0) as f64 / speed;
+                    let due = Duration::from_secs_f64(offset_ms / 1000.0);
+                    if let Some(wait) = due.checked_sub(started_at.elapsed()) {
+                        time::sleep(wait).await;
+                    }
+                }
+
+                let ended = is_program_ended(&message);
+                yield Ok(StreamItem::Message(message));
+                if ended {
+                    yield Ok(StreamItem::ProgramEnded);
+                    return;
+                }
+            }
+
+            if record.is_none() {
+                break;
+            }
+        }
+
+        yield Ok(StreamItem::ProgramEnded);
+    })
+}
+
+/// 再生するフレームの中身
+#[allow(clippy::large_enum_variant)]
+enum Payload {
+    /// 過去ログの 1 セグメント
+    History(Vec<ChunkedMessage>),
+    Message(ChunkedMessage),
+}
+
+/// 再生しない種類 (view の `ChunkedEntry` など) のフレームは `None`
+fn decode_frame(kind: Option<PayloadKind>, data: &[u8]) -> Result<Option<Payload>> {
+    Ok(match kind {
+        Some(PayloadKind::PackedSegment) => {
+            Some(Payload::History(PackedSegment::decode(data)?.messages))
+        }
+        Some(PayloadKind::ChunkedMessage) => Some(Payload::Message(ChunkedMessage::decode(data)?)),
+        _ => None,
+    })
+}
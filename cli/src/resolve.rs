@@ -0,0 +1,101 @@
+use reqwest::{StatusCode, Url};
+
+use crate::client::NdgrClient;
+use crate::error::{ProgramInfoError, ResolveError};
+use crate::program_info::ProgramStatus;
+
+const WATCH_URL_PREFIX: &str = "https://live.nicovideo.jp/watch/";
+
+/// 視聴する番組の指定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgramRef {
+    /// `lv` から始まる番組 ID
+    Program(String),
+    /// `co` / `ch` から始まるコミュニティ・チャンネル ID。放送中の番組を探す必要がある。
+    SocialGroup(String),
+}
+
+impl ProgramRef {
+    /// `lv123` / `co123` / `ch123` のような ID か、視聴ページ・`sp.live`・`nico.ms` の URL を読み取る。
+    /// スキームは省略できる。
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        if let Some(program_ref) = parse_id(input) {
+            return Some(program_ref);
+        }
+
+        let url = if input.contains("://") {
+            Url::parse(input)
+        } else {
+            Url::parse(&format!("https://{input}"))
+        }
+        .ok()?;
+        let mut segments = url.path_segments()?.filter(|segment| !segment.is_empty());
+        let id = match url.host_str()? {
+            "nico.ms" => segments.next()?,
+            "live.nicovideo.jp"
+            | "live2.nicovideo.jp"
+            | "sp.live.nicovideo.jp"
+            | "sp.live2.nicovideo.jp" => match (segments.next()?, segments.next()) {
+                ("watch", Some(id)) => id,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        parse_id(id)
+    }
+
+    pub fn id(&self) -> &str {
+        match self {
+            ProgramRef::Program(id) | ProgramRef::SocialGroup(id) => id,
+        }
+    }
+
+    /// PC 版の視聴ページの URL。コミュニティ・チャンネルなら放送中の番組のページになる。
+    pub fn watch_url(&self) -> String {
+        format!("{WATCH_URL_PREFIX}{}", self.id())
+    }
+}
+
+fn parse_id(input: &str) -> Option<ProgramRef> {
+    let id = input.to_ascii_lowercase();
+    let (prefix, digits) = id.split_at_checked(2)?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    match prefix {
+        "lv" => Some(ProgramRef::Program(id)),
+        "co" | "ch" => Some(ProgramRef::SocialGroup(id)),
+        _ => None,
+    }
+}
+
+/// 既定の [`NdgrClient`] で [`NdgrClient::resolve_watch_url`] を呼ぶ
+pub async fn resolve_watch_url(input: &str) -> Result<String, ResolveError> {
+    NdgrClient::default().resolve_watch_url(input).await
+}
+
+impl NdgrClient {
+    /// [`ProgramRef::parse`] が受け付ける入力を `https://live.nicovideo.jp/watch/lv...` に揃える。
+    /// コミュニティ・チャンネルはページを取得して放送中の番組を探す。
+    pub async fn resolve_watch_url(&self, input: &str) -> Result<String, ResolveError> {
+        let program_ref = ProgramRef::parse(input)
+            .ok_or_else(|| ResolveError::InvalidInput(input.to_string()))?;
+        let ProgramRef::SocialGroup(id) = &program_ref else {
+            return Ok(program_ref.watch_url());
+        };
+
+        let info = match self.fetch_embedded_data(&program_ref.watch_url()).await {
+            Ok(info) => info,
+            Err(ProgramInfoError::HttpStatus(StatusCode::NOT_FOUND)) => {
+                return Err(ResolveError::NotOnAir(id.clone()));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let program_id = &info.program.nicolive_program_id;
+        if info.program.status != ProgramStatus::OnAir || program_id.is_empty() {
+            return Err(ResolveError::NotOnAir(id.clone()));
+        }
+        Ok(format!("{WATCH_URL_PREFIX}{program_id}"))
+    }
+}
@@ -0,0 +1,71 @@
+use protobuf::chat::service::edge::ChunkedMessage;
+
+use crate::dedup::Deduplicator;
+use crate::timeshift::{TimeshiftOptions, message_time};
+use crate::{DEFAULT_DEDUP_CAPACITY, StreamOptions, ViewQuery};
+
+/// 再接続でメッセージサーバーが変わってストリームを張り直すときに、続きから流すための記録。
+/// 最後に受け取ったメッセージの時刻から問い合わせ直し、流した `meta.id` は重複除去に引き継ぐ。
+#[derive(Debug, Clone)]
+pub struct StreamResume {
+    last_at: Option<f64>,
+    seen: Deduplicator,
+}
+
+impl Default for StreamResume {
+    fn default() -> Self {
+        Self {
+            last_at: None,
+            seen: Deduplicator::new(DEFAULT_DEDUP_CAPACITY),
+        }
+    }
+}
+
+impl StreamResume {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 表示済みの過去コメントのように、流さないメッセージの `meta.id` を覚えさせる。
+    /// 時刻は記録しないので、最初に張るストリームは `start` のまま問い合わせる。
+    pub fn extend_seen(&mut self, ids: impl IntoIterator<Item = impl AsRef<str>>) {
+        for id in ids {
+            self.seen.insert(id.as_ref());
+        }
+    }
+
+    /// ストリームから受け取ったメッセージを記録する
+    pub fn record(&mut self, message: &ChunkedMessage) {
+        if let Some(meta) = &message.meta {
+            self.seen.insert(&meta.id);
+        }
+        self.last_at = message_time(message).or(self.last_at);
+    }
+
+    /// 最後に受け取ったメッセージの時刻 (UNIX 秒)
+    pub fn last_at(&self) -> Option<f64> {
+        self.last_at
+    }
+
+    /// 続きから流す設定。まだ何も受け取っていなければ `options.start` から問い合わせる
+    pub fn resume(&self, options: StreamOptions) -> StreamOptions {
+        let mut seen_ids = options.seen_ids;
+        seen_ids.extend(self.seen.ids().map(str::to_string));
+        StreamOptions {
+            start: self
+                .last_at
+                .map_or(options.start, |at| ViewQuery::At(at as i64)),
+            seen_ids,
+            ..options
+        }
+    }
+
+    /// タイムシフトを続きから再生する設定。まだ何も受け取っていなければ `options.start_at` から
+    pub fn resume_timeshift(&self, options: TimeshiftOptions) -> TimeshiftOptions {
+        TimeshiftOptions {
+            start_at: self.last_at.map_or(options.start_at, |at| at as i64),
+            stream: self.resume(options.stream),
+            ..options
+        }
+    }
+}
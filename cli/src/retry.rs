@@ -0,0 +1,88 @@
+use std::fmt;
+use std::time::Duration;
+
+/// 取得に失敗したときの再試行の設定。待ち時間は指数的に伸びる。
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// 1 回目の失敗後の待ち時間
+    pub initial_delay: Duration,
+    /// 待ち時間の上限
+    pub max_delay: Duration,
+    /// 失敗するたびに待ち時間に掛ける倍率
+    pub multiplier: f64,
+    /// 待ち時間を最大この割合だけ前後にずらす (0.0〜1.0)
+    pub jitter: f64,
+    /// 連続してこの回数失敗したら諦める。`None` なら無制限。
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: Some(10),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// `attempt` 回連続で失敗した後の待ち時間 (`attempt` は 1 始まり)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let base = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        let base = base.min(self.max_delay.as_secs_f64());
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 + jitter * (fastrand::f64() * 2.0 - 1.0);
+
+        Duration::from_secs_f64((base * factor).max(0.0))
+    }
+
+    /// `attempt` 回連続で失敗した後にまだ再試行するかどうか
+    pub fn should_retry(&self, attempt: u32) -> bool {
+        self.max_attempts.is_none_or(|max| attempt < max)
+    }
+}
+
+/// ストリームの途中で起きた取得・デコードの失敗
+#[derive(Debug)]
+pub struct StreamError {
+    /// 連続で何回目の失敗か。壊れたフレームを読み飛ばしただけなら 0
+    pub attempt: u32,
+    /// `true` ならストリームは待機後に再試行を続ける。`false` なら諦めてストリームが終わる。
+    pub retrying: bool,
+    pub source: anyhow::Error,
+}
+
+impl StreamError {
+    /// 壊れたフレームを読み飛ばして、そのまま読み続けるときのエラー
+    pub(crate) fn skipped(source: anyhow::Error) -> Self {
+        Self {
+            attempt: 0,
+            retrying: true,
+            source,
+        }
+    }
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.attempt == 0 {
+            return write!(f, "{} (skipped)", self.source);
+        }
+        write!(f, "{} (attempt {}", self.source, self.attempt)?;
+        if !self.retrying {
+            f.write_str(", giving up")?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+
+use protobuf::chat::service::edge::ChunkedMessage;
+use serde::Serialize;
+
+use crate::model::{
+    CommentMode, Enquete, EnqueteStatus, Event, Message, MoveOrder, OperatorComment, ProgramStatus,
+    StateChange, Statistics, TrialPanel,
+};
+
+/// 差分 (`NicoliveState`) を積み上げた、現在の番組の状態
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgramState {
+    pub statistics: Statistics,
+    /// 実施中または結果表示中のアンケート
+    pub enquete: Option<Enquete>,
+    /// 表示中の運営コメント
+    pub marquee: Option<OperatorComment>,
+    /// 最後に受け取った移動指示 (ジャンプ・リダイレクト)
+    pub move_order: Option<MoveOrder>,
+    pub comment_locked: bool,
+    pub comment_mode: CommentMode,
+    pub trial_panel: Option<TrialPanel>,
+    pub ended: bool,
+}
+
+impl Default for ProgramState {
+    fn default() -> Self {
+        Self {
+            statistics: Statistics::default(),
+            enquete: None,
+            marquee: None,
+            move_order: None,
+            comment_locked: false,
+            comment_mode: CommentMode::Normal,
+            trial_panel: None,
+            ended: false,
+        }
+    }
+}
+
+impl fmt::Display for ProgramState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "来場者 {} / コメント {}",
+            self.statistics.viewers.unwrap_or_default(),
+            self.statistics.comments.unwrap_or_default()
+        )?;
+        if self.comment_locked {
+            f.write_str(" / コメント禁止")?;
+        }
+        if let Some(enquete) = &self.enquete {
+            write!(f, " / アンケート: {}", enquete.question)?;
+        }
+        if let Some(marquee) = &self.marquee {
+            write!(f, " / 運営: {}", marquee.content)?;
+        }
+        if self.ended {
+            f.write_str(" / 番組終了")?;
+        }
+        Ok(())
+    }
+}
+
+/// 受け取った状態の差分を [`ProgramState`] に反映していく
+#[derive(Debug, Clone, Default)]
+pub struct StateTracker {
+    state: ProgramState,
+}
+
+impl StateTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> &ProgramState {
+        &self.state
+    }
+
+    /// 状態のメッセージなら反映する。状態が変わったら `true` を返す。
+    pub fn apply_message(&mut self, message: &ChunkedMessage) -> bool {
+        match Message::from_chunked_message(message) {
+            Some(Message {
+                event: Event::StateChange(change),
+                ..
+            }) => self.apply(&change),
+            _ => false,
+        }
+    }
+
+    /// 差分を反映する。状態が変わったら `true` を返す。
+    pub fn apply(&mut self, change: &StateChange) -> bool {
+        let before = self.state.clone();
+        let state = &mut self.state;
+
+        if let Some(statistics) = &change.statistics {
+            // 項目ごとに送られてくるので、来ていない項目は前の値を残す
+            let current = &mut state.statistics;
+            current.viewers = statistics.viewers.or(current.viewers);
+            current.comments = statistics.comments.or(current.comments);
+            current.ad_points = statistics.ad_points.or(current.ad_points);
+            current.gift_points = statistics.gift_points.or(current.gift_points);
+        }
+        if let Some(enquete) = &change.enquete {
+            state.enquete = match enquete.status {
+                EnqueteStatus::Closed => None,
+                _ => Some(enquete.clone()),
+            };
+        }
+        if let Some(marquee) = &change.marquee {
+            state.marquee = marquee.operator_comment.clone();
+        }
+        if let Some(move_order) = &change.move_order {
+            state.move_order = Some(move_order.clone());
+        }
+        if let Some(locked) = change.comment_locked {
+            state.comment_locked = locked;
+        }
+        if let Some(mode) = change.comment_mode {
+            state.comment_mode = mode;
+        }
+        if let Some(panel) = change.trial_panel {
+            state.trial_panel = Some(panel);
+        }
+        if let Some(ProgramStatus::Ended) = change.program_status {
+            state.ended = true;
+        }
+
+        self.state != before
+    }
+}
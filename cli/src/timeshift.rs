@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use futures::pin_mut;
+use futures_core::stream::Stream;
+use futures_util::StreamExt;
+use protobuf::chat::service::edge::ChunkedMessage;
+
+use crate::retry::StreamError;
+use crate::{StreamItem, StreamOptions, ViewQuery, stream_chunked_message_with_options, time};
+
+/// タイムシフト再生の設定
+#[derive(Debug, Clone)]
+pub struct TimeshiftOptions {
+    /// 再生を始める位置 (UNIX 秒)
+    pub start_at: i64,
+    /// 再生を終える位置 (UNIX 秒)。`None` なら番組の最後まで
+    pub end_at: Option<i64>,
+    /// 再生速度の倍率。`f64::INFINITY` なら待たずに流す。0 以下や NaN はエラーになる
+    pub speed: f64,
+    /// `start` は `start_at` で上書きされる
+    pub stream: StreamOptions,
+}
+
+impl TimeshiftOptions {
+    /// `start_at` から最後まで等速で再生する
+    pub fn new(start_at: i64) -> Self {
+        Self {
+            start_at,
+            end_at: None,
+            speed: 1.0,
+            stream: StreamOptions::default(),
+        }
+    }
+}
+
+/// 終了した番組のコメントを `start_at` から、投稿時刻 (`meta.at`) の間隔に合わせて流す。
+/// シークや速度変更はこのストリームを新しい `start_at` で作り直して行う。
+/// `end_at` を過ぎたら [`StreamItem::ProgramEnded`] を流して終わる。
+pub async fn stream_timeshift(
+    view_uri: &str,
+    options: TimeshiftOptions,
+) -> impl Stream<Item = Result<StreamItem, StreamError>> + use<> {
+    let view_uri = view_uri.to_string();
+
+    async_stream::stream! {
+        if options.speed.is_nan() || options.speed <= 0.0 {
+            yield Err(StreamError {
+                attempt: 1,
+                retrying: false,
+                source: anyhow::anyhow!("timeshift speed must be positive: {}", options.speed),
+            });
+            return;
+        }
+
+        let stream_options = StreamOptions {
+            start: ViewQuery::At(options.start_at),
+            ..options.stream
+        };
+        let stream = stream_chunked_message_with_options(&view_uri, stream_options).await;
+        pin_mut!(stream);
+
+        // 最後に流したメッセージの時刻。待ち時間はここからの差分で決める
+        let mut position = options.start_at as f64;
+
+        while let Some(item) = stream.next().await {
+            if let Ok(StreamItem::Message(message)) = &item
+                && let Some(at) = message_time(message)
+            {
+                // セグメントは要求した位置より前から始まることがある
+                if at < options.start_at as f64 {
+                    continue;
+                }
+                if options.end_at.is_some_and(|end_at| at > end_at as f64) {
+                    yield Ok(StreamItem::ProgramEnded);
+                    return;
+                }
+
+                let wait = (at - position) / options.speed;
+                if wait > 0.0 && wait.is_finite() {
+                    time::sleep(Duration::from_secs_f64(wait)).await;
+                }
+                position = position.max(at);
+            }
+            yield item;
+        }
+    }
+}
+
+/// `meta.at` を UNIX 秒 (小数) で返す
+pub fn message_time(message: &ChunkedMessage) -> Option<f64> {
+    let at = message.meta.as_ref()?.at.as_ref()?;
+    Some(at.seconds as f64 + at.nanos as f64 / 1e9)
+}
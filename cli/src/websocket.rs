@@ -1,35 +1,94 @@
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use base64::prelude::{BASE64_STANDARD, Engine as _};
+use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use reqwest::header::COOKIE;
 use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
-use tokio::sync::mpsc::{self, Sender};
-use tokio_tungstenite::connect_async;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::select;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::{broadcast, oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, Interval};
+use tokio_socks::TargetAddr;
+use tokio_socks::tcp::Socks5Stream;
 use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, client_async_tls, connect_async};
+use tokio_util::sync::CancellationToken;
+
+use crate::client::NdgrClient;
+
+/// バックグラウンドの WebSocket セッションで起きたことの通知
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebSocketEvent {
+    /// サーバーから `disconnect` を受け取った。この後に `Closed` が続く。
+    Disconnected { reason: String },
+    /// サーバーの指示で再接続を待っている
+    Reconnecting { wait_time_sec: i64 },
+    /// セッションは継続しているが、処理できないフレームや送信失敗があった
+    Error { message: String },
+    /// セッションが終了した。以降イベントは送られない。
+    Closed,
+}
+
+/// 接続の状態
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// サーバーの指示で再接続を待っている
+    Reconnecting {
+        wait_time_sec: i64,
+    },
+    /// サーバーから `disconnect` を受け取った
+    Disconnected {
+        reason: String,
+    },
+    /// セッションが終了した
+    Closed,
+}
+
+/// [`WebSocketClient::subscribe_status`] で受け取るセッションの状態
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionStatus {
+    pub connection: ConnectionState,
+    /// `seat` で指示された座席維持 (`keepSeat`) の間隔
+    pub keep_interval_sec: i64,
+    /// 最後に `keepSeat` を送った時刻
+    pub last_keep_seat: Option<SystemTime>,
+}
 
 pub struct WebSocketClient {
-    tx: Sender<String>,
-    view_uri: String,
+    tx: Sender<PostRequest>,
+    view_uri: watch::Receiver<String>,
+    status: watch::Receiver<SessionStatus>,
+    events: broadcast::Sender<WebSocketEvent>,
+    cancel: CancellationToken,
+    task: Option<JoinHandle<()>>,
 }
 
 impl WebSocketClient {
+    /// 既定の [`NdgrClient`] (未ログイン) で接続する
     pub async fn new(web_socket_url: &str) -> Result<Self> {
-        let (ws_stream, _) = connect_async(web_socket_url).await?;
+        Self::connect(&NdgrClient::default(), web_socket_url).await
+    }
+
+    /// `client` の Cookie・ヘッダー・プロキシを使って接続する。再接続でも同じものを使う。
+    pub async fn connect(client: &NdgrClient, web_socket_url: &str) -> Result<Self> {
+        let ws_stream = connect(web_socket_url, client).await?;
 
         let (mut write, mut read) = ws_stream.split();
 
-        let (tx, mut rx) = mpsc::channel(100);
+        let (tx, rx) = mpsc::channel(100);
 
-        write
-            .send(Message::Text(
-                serde_json::to_string(&InitialConnectionMessage {
-                    r#type: "startWatching".to_string(),
-                    data: InitialConnectionData { reconnect: false },
-                })?
-                .into(),
-            ))
-            .await?;
+        start_watching(&mut write, false).await?;
 
         let mut view_uri = None;
         let mut keep_interval_sec = None;
@@ -44,6 +103,9 @@ impl WebSocketClient {
                     ResponseMessage::Seat { data } => {
                         keep_interval_sec = Some(data.keep_interval_sec);
                     }
+                    ResponseMessage::Disconnect { data } => {
+                        return Err(anyhow::anyhow!("disconnected: {}", data.reason));
+                    }
                     _ => (),
                 }
                 if view_uri.is_some() && keep_interval_sec.is_some() {
@@ -52,93 +114,491 @@ impl WebSocketClient {
             }
         }
 
-        let view_uri = view_uri.unwrap();
-        let keep_interval_sec = keep_interval_sec.unwrap();
-
-        let write = Arc::new(Mutex::new(write));
-
-        let write_clone = Arc::clone(&write);
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(tokio::time::Duration::from_secs(keep_interval_sec as u64))
-                    .await;
-                write_clone
-                    .lock()
-                    .await
-                    .send(Message::Text(r#"{"type":"keepSeat"}"#.into()))
-                    .await
-                    .unwrap();
-            }
+        let view_uri = view_uri.ok_or_else(|| anyhow::anyhow!("view uri not found"))?;
+        let keep_interval_sec =
+            keep_interval_sec.ok_or_else(|| anyhow::anyhow!("seat not found"))?;
+
+        let (view_uri_tx, view_uri_rx) = watch::channel(view_uri);
+        let (status_tx, status_rx) = watch::channel(SessionStatus {
+            connection: ConnectionState::Connected,
+            keep_interval_sec,
+            last_keep_seat: None,
         });
+        let (events, _) = broadcast::channel(16);
+        let cancel = CancellationToken::new();
+
+        let session = Session {
+            web_socket_url: web_socket_url.to_string(),
+            client: client.clone(),
+            keep_interval_sec,
+            rx,
+            pending_posts: VecDeque::new(),
+            view_uri_tx,
+            status_tx,
+            events: events.clone(),
+            cancel: cancel.clone(),
+        };
+        let task = tokio::spawn(session.run(write, read));
 
-        let write_clone = Arc::clone(&write);
-        tokio::spawn(async move {
-            while let Some(message) = rx.recv().await {
-                write_clone
-                    .lock()
-                    .await
-                    // TODO
-                    .send(Message::Text(
-                        format!(
-                            r#"{{"type":"postComment","data":{{"text":"{}"}}}}"#,
-                            message
-                        )
-                        .into(),
-                    ))
-                    .await
-                    .unwrap();
+        Ok(Self {
+            tx,
+            view_uri: view_uri_rx,
+            status: status_rx,
+            events,
+            cancel,
+            task: Some(task),
+        })
+    }
+
+    /// コメントを投稿し、サーバーの `postCommentResult` を待つ
+    pub async fn post(&self, comment: PostComment) -> Result<PostCommentResult> {
+        self.poster().post(comment).await
+    }
+
+    /// 別タスクから投稿するためのハンドル
+    pub fn poster(&self) -> CommentPoster {
+        CommentPoster {
+            tx: self.tx.clone(),
+        }
+    }
+
+    /// 現在のメッセージサーバーの view URI
+    pub fn view_uri(&self) -> String {
+        self.view_uri.borrow().clone()
+    }
+
+    /// 再接続で view URI が変わったときに通知を受け取る
+    pub fn subscribe_view_uri(&self) -> watch::Receiver<String> {
+        self.view_uri.clone()
+    }
+
+    /// 接続と座席維持の状態が変わったときに通知を受け取る
+    pub fn subscribe_status(&self) -> watch::Receiver<SessionStatus> {
+        self.status.clone()
+    }
+
+    /// セッションの切断・エラーの通知を受け取る
+    pub fn subscribe_events(&self) -> broadcast::Receiver<WebSocketEvent> {
+        self.events.subscribe()
+    }
+
+    /// キャンセルするとバックグラウンドのセッションが WebSocket を閉じて終了する。
+    /// クライアントを drop した場合も同様にキャンセルされる。
+    pub fn shutdown_handle(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// セッションを閉じ、バックグラウンドのタスクが終わるまで待つ
+    pub async fn shutdown(mut self) {
+        self.cancel.cancel();
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for WebSocketClient {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+#[derive(Clone)]
+pub struct CommentPoster {
+    tx: Sender<PostRequest>,
+}
+
+impl CommentPoster {
+    /// サーバーに拒否された場合は [`PostCommentError`] を返す。
+    /// 10 秒以内に応答がなければ失敗する。
+    pub async fn post(&self, comment: PostComment) -> Result<PostCommentResult> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.tx
+            .send(PostRequest { comment, result_tx })
+            .await
+            .map_err(|_| anyhow::anyhow!("session closed"))?;
+        result_rx
+            .await
+            .map_err(|_| anyhow::anyhow!("session closed"))?
+    }
+}
+
+struct PostRequest {
+    comment: PostComment,
+    result_tx: oneshot::Sender<Result<PostCommentResult>>,
+}
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// 接続後のバックグラウンド処理 (座席維持・コメント投稿・ping 応答・再接続) をまとめて受け持つ
+struct Session {
+    web_socket_url: String,
+    /// 再接続に使うクライアント
+    client: NdgrClient,
+    keep_interval_sec: i64,
+    rx: Receiver<PostRequest>,
+    /// `postCommentResult` / `error` を待っている投稿と応答の期限。サーバーは送った順に応答する。
+    pending_posts: VecDeque<(Instant, oneshot::Sender<Result<PostCommentResult>>)>,
+    view_uri_tx: watch::Sender<String>,
+    status_tx: watch::Sender<SessionStatus>,
+    events: broadcast::Sender<WebSocketEvent>,
+    cancel: CancellationToken,
+}
+
+/// 1 本の WebSocket 接続が終わった理由
+enum ServeEnd {
+    Closed,
+    Reconnect(ReconnectData),
+}
+
+impl Session {
+    async fn run(mut self, write: SplitSink<WsStream, Message>, read: SplitStream<WsStream>) {
+        let result = self.run_inner(write, read).await;
+        // 応答が来ないまま終わった投稿は `CommentPoster::post` 側で session closed になる
+        self.pending_posts.clear();
+        if let Err(e) = result {
+            self.emit(WebSocketEvent::Error {
+                message: e.to_string(),
+            });
+        }
+        // `disconnect` で終わった場合はその理由を残す
+        self.status_tx.send_modify(|status| {
+            if !matches!(status.connection, ConnectionState::Disconnected { .. }) {
+                status.connection = ConnectionState::Closed;
             }
         });
+        self.emit(WebSocketEvent::Closed);
+    }
+
+    async fn run_inner(
+        &mut self,
+        mut write: SplitSink<WsStream, Message>,
+        mut read: SplitStream<WsStream>,
+    ) -> Result<()> {
+        loop {
+            let ServeEnd::Reconnect(reconnect) = self.serve(&mut write, &mut read).await? else {
+                return Ok(());
+            };
+
+            // 古い接続で送った投稿の応答はもう届かない
+            for (_, result_tx) in self.pending_posts.drain(..) {
+                let _ = result_tx.send(Err(anyhow::anyhow!("connection reset by reconnect")));
+            }
+            self.emit(WebSocketEvent::Reconnecting {
+                wait_time_sec: reconnect.wait_time_sec,
+            });
+            self.set_connection(ConnectionState::Reconnecting {
+                wait_time_sec: reconnect.wait_time_sec,
+            });
+            let wait_time = Duration::from_secs(reconnect.wait_time_sec.max(0) as u64);
+            select! {
+                _ = tokio::time::sleep(wait_time) => (),
+                _ = self.cancel.cancelled() => return Ok(()),
+            }
+
+            self.web_socket_url =
+                with_audience_token(&self.web_socket_url, &reconnect.audience_token)?;
+            let ws_stream = connect(&self.web_socket_url, &self.client).await?;
+            (write, read) = ws_stream.split();
+
+            start_watching(&mut write, true).await?;
+            self.set_connection(ConnectionState::Connected);
+        }
+    }
+
+    /// 1 本の WebSocket 接続を処理する
+    async fn serve(
+        &mut self,
+        write: &mut SplitSink<WsStream, Message>,
+        read: &mut SplitStream<WsStream>,
+    ) -> Result<ServeEnd> {
+        let mut keep_seat = keep_seat_interval(self.keep_interval_sec);
 
-        tokio::spawn(async move {
-            while let Some(msg) = read.next().await {
-                if let Ok(Message::Text(text)) = msg {
-                    let response: ResponseMessage = serde_json::from_str(&text).unwrap();
+        loop {
+            let post_deadline = self.pending_posts.front().map(|(deadline, _)| *deadline);
+            let post_timeout = tokio::time::sleep_until(post_deadline.unwrap_or_else(Instant::now));
+            select! {
+                msg = read.next() => {
+                    let Some(msg) = msg else {
+                        return Ok(ServeEnd::Closed);
+                    };
+                    let Message::Text(text) = msg? else {
+                        continue;
+                    };
+                    let response: ResponseMessage = match serde_json::from_str(&text) {
+                        Ok(response) => response,
+                        Err(e) => {
+                            self.emit(WebSocketEvent::Error {
+                                message: format!("invalid message: {e}"),
+                            });
+                            continue;
+                        }
+                    };
                     match response {
                         ResponseMessage::Ping => {
-                            write
-                                .lock()
-                                .await
-                                .send(Message::Text(r#"{"type":"pong"}"#.into()))
-                                .await
-                                .unwrap();
+                            write.send(Message::Text(r#"{"type":"pong"}"#.into())).await?;
+                        }
+                        ResponseMessage::MessageServer { data } => {
+                            self.view_uri_tx.send_if_modified(|view_uri| {
+                                if *view_uri == data.view_uri {
+                                    return false;
+                                }
+                                *view_uri = data.view_uri;
+                                true
+                            });
                         }
-                        ResponseMessage::Reconnect { data: _ } => {
-                            // TODO
+                        ResponseMessage::Seat { data } => {
+                            self.keep_interval_sec = data.keep_interval_sec;
+                            keep_seat = keep_seat_interval(self.keep_interval_sec);
+                            self.status_tx.send_modify(|status| {
+                                status.keep_interval_sec = data.keep_interval_sec;
+                            });
+                        }
+                        ResponseMessage::Reconnect { data } => {
+                            return Ok(ServeEnd::Reconnect(data));
+                        }
+                        ResponseMessage::Disconnect { data } => {
+                            self.set_connection(ConnectionState::Disconnected {
+                                reason: data.reason.clone(),
+                            });
+                            self.emit(WebSocketEvent::Disconnected { reason: data.reason });
+                            return Ok(ServeEnd::Closed);
+                        }
+                        ResponseMessage::PostCommentResult { data } => {
+                            if let Some((_, result_tx)) = self.pending_posts.pop_front() {
+                                let _ = result_tx.send(Ok(data));
+                            }
+                        }
+                        ResponseMessage::Error { data } => {
+                            // 投稿と関係ないエラーで待っている投稿を失敗させない
+                            let pending = if is_post_error(&data.code) {
+                                self.pending_posts.pop_front()
+                            } else {
+                                None
+                            };
+                            if let Some((_, result_tx)) = pending {
+                                let error = PostCommentError { code: data.code };
+                                let _ = result_tx.send(Err(error.into()));
+                            } else {
+                                self.emit(WebSocketEvent::Error {
+                                    message: format!("server error: {}", data.code),
+                                });
+                            }
                         }
                         _ => (),
                     }
                 }
+                _ = keep_seat.tick() => {
+                    write.send(Message::Text(r#"{"type":"keepSeat"}"#.into())).await?;
+                    self.status_tx.send_modify(|status| {
+                        status.last_keep_seat = Some(SystemTime::now());
+                    });
+                }
+                Some(request) = self.rx.recv() => {
+                    let message = PostCommentMessage::new(request.comment);
+                    let result = match serde_json::to_string(&message) {
+                        Ok(text) => {
+                            write.send(Message::Text(text.into())).await.map_err(Into::into)
+                        }
+                        Err(e) => Err(e.into()),
+                    };
+                    match result {
+                        Ok(()) => {
+                            let deadline = Instant::now() + POST_TIMEOUT;
+                            self.pending_posts.push_back((deadline, request.result_tx));
+                        }
+                        Err(e) => {
+                            let _ = request.result_tx.send(Err(e));
+                        }
+                    }
+                }
+                _ = post_timeout, if post_deadline.is_some() => {
+                    if let Some((_, result_tx)) = self.pending_posts.pop_front() {
+                        let _ = result_tx.send(Err(anyhow::anyhow!("post timed out")));
+                    }
+                }
+                _ = self.cancel.cancelled() => {
+                    let _ = write.send(Message::Close(None)).await;
+                    return Ok(ServeEnd::Closed);
+                }
             }
-        });
+        }
+    }
 
-        Ok(Self { tx, view_uri })
+    fn set_connection(&self, connection: ConnectionState) {
+        self.status_tx
+            .send_modify(|status| status.connection = connection);
     }
 
-    pub async fn post(&self, comment: &str) -> Result<()> {
-        self.tx.send(comment.to_string()).await?;
-        Ok(())
+    fn emit(&self, event: WebSocketEvent) {
+        // 購読者がいなくてもセッションは続ける
+        let _ = self.events.send(event);
     }
+}
+
+/// 投稿への応答を待つ時間
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
 
-    pub fn view_uri(&self) -> &String {
-        &self.view_uri
+/// 投稿に対する応答として返ってくる `error` のコード
+fn is_post_error(code: &str) -> bool {
+    matches!(
+        code,
+        "COMMENT_LOCKED" | "COMMENT_POST_NOT_ALLOWED" | "TOO_FREQUENT" | "TOO_LONG_COMMENT"
+    )
+}
+
+fn keep_seat_interval(keep_interval_sec: i64) -> Interval {
+    let period = Duration::from_secs(keep_interval_sec.max(1) as u64);
+    tokio::time::interval_at(Instant::now() + period, period)
+}
+
+/// `connect_timeout` はプロキシの有無にかかわらず、ハンドシェイクまでを含めて掛ける
+async fn connect(web_socket_url: &str, client: &NdgrClient) -> Result<WsStream> {
+    let connecting = open_web_socket(web_socket_url, client);
+    match client.connect_timeout() {
+        Some(timeout) => tokio::time::timeout(timeout, connecting)
+            .await
+            .context("timed out connecting to websocket")?,
+        None => connecting.await,
     }
 }
 
-pub async fn fetch_ndgr_view_uri(web_socket_url: &str) -> Result<String> {
-    let (ws_stream, _) = connect_async(web_socket_url).await?;
+async fn open_web_socket(web_socket_url: &str, client: &NdgrClient) -> Result<WsStream> {
+    let mut request = web_socket_url.into_client_request()?;
+    request.headers_mut().extend(
+        client
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone())),
+    );
+    if let Some(cookie) = client.cookie_for(web_socket_url) {
+        request.headers_mut().insert(COOKIE, cookie.clone());
+    }
 
-    let (mut write, mut read) = ws_stream.split();
+    let Some(proxy) = client.proxy() else {
+        let (ws_stream, _) = connect_async(request).await?;
+        return Ok(ws_stream);
+    };
 
+    let uri = request.uri();
+    let host = uri.host().context("websocket url has no host")?.to_string();
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("wss") {
+            443
+        } else {
+            80
+        });
+    let stream = connect_via_proxy(proxy, &host, port).await?;
+    let (ws_stream, _) = client_async_tls(request, stream).await?;
+    Ok(ws_stream)
+}
+
+/// プロキシを経由して `host:port` への TCP 接続を張る
+async fn connect_via_proxy(proxy: &Url, host: &str, port: u16) -> Result<TcpStream> {
+    let proxy_host = proxy.host_str().context("proxy url has no host")?;
+    // URL の中では `@` や `:` がパーセントエンコードされている
+    let username = percent_decode_str(proxy.username())
+        .decode_utf8()
+        .context("invalid proxy username")?;
+    let password = percent_decode_str(proxy.password().unwrap_or_default())
+        .decode_utf8()
+        .context("invalid proxy password")?;
+
+    match proxy.scheme() {
+        "http" => {
+            let proxy_port = proxy.port_or_known_default().unwrap_or(80);
+            let mut stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+
+            let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+            if !username.is_empty() {
+                let credentials = BASE64_STANDARD.encode(format!("{username}:{password}"));
+                request.push_str(&format!("Proxy-Authorization: Basic {credentials}\r\n"));
+            }
+            request.push_str("\r\n");
+            stream.write_all(request.as_bytes()).await?;
+
+            // トンネルの先のデータを読みすぎないように、ヘッダーの終わりまで 1 バイトずつ読む
+            let mut response = Vec::new();
+            while !response.ends_with(b"\r\n\r\n") {
+                if response.len() > 8192 {
+                    anyhow::bail!("proxy response header too long");
+                }
+                let mut byte = [0; 1];
+                if stream.read(&mut byte).await? == 0 {
+                    anyhow::bail!("proxy closed the connection");
+                }
+                response.push(byte[0]);
+            }
+            let status_line = String::from_utf8_lossy(&response);
+            let status_line = status_line.lines().next().unwrap_or_default();
+            if status_line.split_whitespace().nth(1) != Some("200") {
+                anyhow::bail!("proxy refused CONNECT: {status_line}");
+            }
+            Ok(stream)
+        }
+        scheme @ ("socks5" | "socks5h") => {
+            let proxy_port = proxy.port().unwrap_or(1080);
+            let proxy_addr = (proxy_host, proxy_port);
+            // socks5 は名前解決を手元で、socks5h はプロキシ側で行う
+            let target = if scheme == "socks5" {
+                let addr = tokio::net::lookup_host((host, port))
+                    .await?
+                    .next()
+                    .with_context(|| format!("failed to resolve {host}"))?;
+                TargetAddr::Ip(addr)
+            } else {
+                TargetAddr::Domain(host.into(), port)
+            };
+            let stream = if username.is_empty() {
+                Socks5Stream::connect(proxy_addr, target).await?
+            } else {
+                Socks5Stream::connect_with_password(proxy_addr, target, &username, &password)
+                    .await?
+            };
+            Ok(stream.into_inner())
+        }
+        scheme => anyhow::bail!("unsupported proxy scheme for websocket: {scheme}"),
+    }
+}
+
+async fn start_watching(write: &mut SplitSink<WsStream, Message>, reconnect: bool) -> Result<()> {
     write
         .send(Message::Text(
             serde_json::to_string(&InitialConnectionMessage {
                 r#type: "startWatching".to_string(),
-                data: InitialConnectionData { reconnect: false },
+                data: InitialConnectionData { reconnect },
             })?
             .into(),
         ))
         .await?;
+    Ok(())
+}
+
+/// WebSocket URL の `audience_token` を再接続用のトークンに差し替える
+fn with_audience_token(web_socket_url: &str, audience_token: &str) -> Result<String> {
+    let mut url = Url::parse(web_socket_url)?;
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "audience_token")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    url.query_pairs_mut()
+        .clear()
+        .append_pair("audience_token", audience_token)
+        .extend_pairs(pairs);
+
+    Ok(url.into())
+}
+
+/// 既定の [`NdgrClient`] で接続し、最初に届いた view URI を返す
+pub async fn fetch_ndgr_view_uri(web_socket_url: &str) -> Result<String> {
+    let ws_stream = connect(web_socket_url, &NdgrClient::default()).await?;
+
+    let (mut write, mut read) = ws_stream.split();
+
+    start_watching(&mut write, false).await?;
 
     while let Some(msg) = read.next().await {
         if let Ok(Message::Text(text)) = msg {
@@ -174,10 +634,18 @@ enum ResponseMessage {
         data: SeatData,
     },
     Ping,
-    #[allow(dead_code)]
     Reconnect {
         data: ReconnectData,
     },
+    Disconnect {
+        data: DisconnectData,
+    },
+    PostCommentResult {
+        data: PostCommentResult,
+    },
+    Error {
+        data: ErrorData,
+    },
     ServerTime,
     Stream,
     Schedule,
@@ -200,8 +668,140 @@ struct SeatData {
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-#[allow(dead_code)]
 struct ReconnectData {
     audience_token: String,
     wait_time_sec: i64,
 }
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DisconnectData {
+    reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorData {
+    code: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PostCommentMessage {
+    r#type: &'static str,
+    data: PostComment,
+}
+
+impl PostCommentMessage {
+    fn new(comment: PostComment) -> Self {
+        Self {
+            r#type: "postComment",
+            data: comment,
+        }
+    }
+}
+
+/// `postComment` で送るコメント
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostComment {
+    pub text: String,
+    /// 番組の `vposBaseTime` からの経過時間 (1/100 秒)。[`vpos_since`] で求められる。
+    pub vpos: i64,
+    /// 184 (匿名) で投稿するかどうか
+    pub is_anonymous: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<CommentColor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<CommentSize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<CommentPosition>,
+}
+
+impl PostComment {
+    /// 匿名・コマンドなしのコメント
+    pub fn new(text: impl Into<String>, vpos: i64) -> Self {
+        Self {
+            text: text.into(),
+            vpos,
+            is_anonymous: true,
+            color: None,
+            size: None,
+            position: None,
+        }
+    }
+}
+
+/// `vpos_base_time` (UNIX 秒) から現在までの vpos
+pub fn vpos_since(vpos_base_time: i64) -> i64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    ((now - vpos_base_time * 1000) / 10).max(0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentColor {
+    White,
+    Red,
+    Pink,
+    Orange,
+    Yellow,
+    Green,
+    Cyan,
+    Blue,
+    Purple,
+    Black,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentSize {
+    Big,
+    Medium,
+    Small,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentPosition {
+    Ue,
+    Naka,
+    Shita,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostCommentResult {
+    pub chat: PostedChat,
+}
+
+/// サーバーが受け付けたコメント
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostedChat {
+    pub content: String,
+    #[serde(default)]
+    pub mail: String,
+    #[serde(default)]
+    pub anonymity: i64,
+    /// NG などで自分にしか見えない状態になったかどうか
+    #[serde(default)]
+    pub restricted: bool,
+}
+
+/// サーバーがコメントを拒否したときの `error` メッセージ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostCommentError {
+    /// `COMMENT_LOCKED` や `TOO_FREQUENT` など
+    pub code: String,
+}
+
+impl fmt::Display for PostCommentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "comment rejected: {}", self.code)
+    }
+}
+
+impl std::error::Error for PostCommentError {}
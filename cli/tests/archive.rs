@@ -0,0 +1,41 @@
+//! 壊れたアーカイブや不正な再生設定を、確保や再生を始める前に弾けるか確かめる
+
+use std::io::Cursor;
+
+use ndgr_client::archive::{ArchiveReader, ArchiveWriter, MAGIC};
+use ndgr_client::replay::{self, Pacing, ReplayOptions};
+
+#[test]
+fn rejects_oversized_record() {
+    let mut bytes = MAGIC.to_vec();
+    // 1 TiB のレコードを示す長さ
+    let mut len: u64 = 1 << 40;
+    while len >= 0x80 {
+        bytes.push((len as u8) | 0x80);
+        len >>= 7;
+    }
+    bytes.push(len as u8);
+
+    let mut reader = ArchiveReader::new(Cursor::new(bytes)).unwrap();
+    let error = reader.read().unwrap_err();
+    assert!(error.to_string().contains("record too large"), "{error}");
+}
+
+#[tokio::test]
+async fn rejects_invalid_speed() {
+    let path = std::env::temp_dir().join(format!("ndgr-speed-{}.ndgr", std::process::id()));
+    ArchiveWriter::create(&path, None)
+        .unwrap()
+        .finish()
+        .unwrap();
+
+    for speed in [0.0, -1.0, f64::NAN] {
+        let options = ReplayOptions {
+            pacing: Pacing::Speed(speed),
+            ..ReplayOptions::default()
+        };
+        assert!(replay::open_with_options(&path, options).await.is_err());
+    }
+    assert!(replay::open(&path).await.is_ok());
+    std::fs::remove_file(&path).unwrap();
+}
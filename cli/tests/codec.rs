@@ -0,0 +1,133 @@
+//! [`LengthDelimitedProtobufDecoder`] が「続きを待つ」と「壊れている」を区別できているか確かめる
+
+use bytes::{BufMut, BytesMut};
+use futures::StreamExt;
+use ndgr_client::codec::{DecodeError, LengthDelimitedProtobufDecoder};
+use ndgr_client::fetch_chunked_message;
+use ndgr_mock_server::{Fixture, MockServer, chat};
+use prost::Message;
+use protobuf::chat::service::edge::ChunkedMessage;
+use tokio_util::codec::{Decoder, FramedRead};
+
+fn encode(messages: &[ChunkedMessage]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for message in messages {
+        message.encode_length_delimited(&mut bytes).unwrap();
+    }
+    bytes
+}
+
+fn id(message: &ChunkedMessage) -> &str {
+    &message.meta.as_ref().unwrap().id
+}
+
+#[test]
+fn waits_for_split_frames() {
+    let bytes = encode(&[chat("m1", 1, "一", 0), chat("m2", 2, "二", 0)]);
+    let mut decoder = LengthDelimitedProtobufDecoder::<ChunkedMessage>::new();
+    let mut buffer = BytesMut::new();
+    let mut ids = Vec::new();
+
+    // 1 バイトずつ届いても、揃った時点でちょうど 1 件ずつ取り出せる
+    for byte in bytes {
+        buffer.put_u8(byte);
+        while let Some(message) = decoder.decode(&mut buffer).unwrap() {
+            ids.push(id(&message).to_string());
+        }
+    }
+
+    assert_eq!(ids, ["m1", "m2"]);
+    assert!(buffer.is_empty());
+    assert!(decoder.decode_eof(&mut buffer).unwrap().is_none());
+}
+
+#[test]
+fn corrupt_frame_is_skipped() {
+    let mut bytes = encode(&[chat("m1", 1, "一", 0)]);
+    // 長さは正しいが中身が protobuf として読めないフレーム
+    bytes.extend_from_slice(&[2, 0xff, 0xff]);
+    bytes.extend_from_slice(&encode(&[chat("m2", 2, "二", 0)]));
+    let mut decoder = LengthDelimitedProtobufDecoder::<ChunkedMessage>::new();
+    let mut buffer = BytesMut::from(&bytes[..]);
+
+    assert_eq!(id(&decoder.decode(&mut buffer).unwrap().unwrap()), "m1");
+    let error = decoder.decode(&mut buffer).unwrap_err();
+    assert!(matches!(error, DecodeError::Message(_)));
+    assert!(error.is_recoverable());
+    assert_eq!(id(&decoder.decode(&mut buffer).unwrap().unwrap()), "m2");
+}
+
+#[test]
+fn rejects_oversized_frame_before_buffering() {
+    let mut decoder = LengthDelimitedProtobufDecoder::<ChunkedMessage>::with_max_frame_len(16);
+    let mut buffer = BytesMut::new();
+    prost::encode_length_delimiter(17, &mut buffer).unwrap();
+
+    let error = decoder.decode(&mut buffer).unwrap_err();
+    assert!(matches!(
+        error,
+        DecodeError::FrameTooLarge { len: 17, max: 16 }
+    ));
+    assert!(!error.is_recoverable());
+}
+
+#[test]
+fn rejects_invalid_length() {
+    let mut decoder = LengthDelimitedProtobufDecoder::<ChunkedMessage>::new();
+
+    // 継続ビットが立ったままの 9 バイトは、まだ続きが来るかもしれない
+    let mut buffer = BytesMut::from(&[0xff; 9][..]);
+    assert!(decoder.decode(&mut buffer).unwrap().is_none());
+
+    buffer.put_u8(0xff);
+    let error = decoder.decode(&mut buffer).unwrap_err();
+    assert!(matches!(error, DecodeError::InvalidLength));
+}
+
+#[test]
+fn truncated_at_eof() {
+    let bytes = encode(&[chat("m1", 1, "一", 0)]);
+    let mut decoder = LengthDelimitedProtobufDecoder::<ChunkedMessage>::new();
+    let mut buffer = BytesMut::from(&bytes[..bytes.len() - 1]);
+
+    assert!(decoder.decode(&mut buffer).unwrap().is_none());
+    let error = decoder.decode_eof(&mut buffer).unwrap_err();
+    assert!(matches!(error, DecodeError::Truncated(len) if len == bytes.len() - 1));
+}
+
+#[tokio::test]
+async fn framed_read() {
+    let bytes = encode(&[chat("m1", 1, "一", 0), chat("m2", 2, "二", 0)]);
+    let framed = FramedRead::new(
+        &bytes[..],
+        LengthDelimitedProtobufDecoder::<ChunkedMessage>::new(),
+    );
+
+    let ids: Vec<String> = framed
+        .map(|message| id(&message.unwrap()).to_string())
+        .collect()
+        .await;
+
+    assert_eq!(ids, ["m1", "m2"]);
+}
+
+#[tokio::test]
+async fn http_stream_reports_corrupt_frames() {
+    let mut body = encode(&[chat("m1", 1, "一", 0)]);
+    body.extend_from_slice(&[2, 0xff, 0xff]);
+    body.extend_from_slice(&encode(&[chat("m2", 2, "二", 0)]));
+    body.push(0x05);
+    let mut fixture = Fixture::new("lv1", 0);
+    fixture.raw.insert("corrupt".to_string(), body);
+    let server = MockServer::start(fixture).await.unwrap();
+
+    let stream = fetch_chunked_message(&format!("{}/raw/corrupt", server.base_url())).await;
+    let items: Vec<_> = stream.collect().await;
+
+    assert_eq!(items.len(), 4);
+    assert_eq!(id(items[0].as_ref().unwrap()), "m1");
+    assert!(items[1].is_err());
+    assert_eq!(id(items[2].as_ref().unwrap()), "m2");
+    let error = items[3].as_ref().unwrap_err().downcast_ref::<DecodeError>();
+    assert!(matches!(error, Some(DecodeError::Truncated(1))));
+}
@@ -0,0 +1,230 @@
+//! [`CommentBuffer`] の折り返しが書記素クラスタを壊さず、幅が変わったりコメントが届いたりしても表示位置を保てるか確かめる
+
+use ndgr_client::comment_buffer::{Comment, CommentBuffer, SearchDirection};
+
+fn visible(buffer: &CommentBuffer) -> Vec<&str> {
+    buffer
+        .visible()
+        .map(|(_, line)| line.text.as_str())
+        .collect()
+}
+
+#[test]
+fn wraps_by_display_width() {
+    let mut buffer = CommentBuffer::new(4, 10);
+    buffer.push("あいうabc".to_string());
+    assert_eq!(visible(&buffer), ["あい", "うab", "c"]);
+}
+
+#[test]
+fn keeps_grapheme_clusters_together() {
+    let mut buffer = CommentBuffer::new(3, 10);
+    // 結合文字付きの e と、ZWJ でつないだ家族の絵文字 (幅 2)
+    buffer.push("ae\u{301}👨\u{200d}👩\u{200d}👧b".to_string());
+    assert_eq!(visible(&buffer), ["ae\u{301}", "👨\u{200d}👩\u{200d}👧b"]);
+}
+
+#[test]
+fn replaces_control_characters() {
+    let mut buffer = CommentBuffer::new(10, 10);
+    buffer.push("一行目\n二行目".to_string());
+    assert_eq!(visible(&buffer), ["一行目 二", "行目"]);
+}
+
+#[test]
+fn columns_only_on_first_line() {
+    let mut buffer = CommentBuffer::new(2, 10);
+    buffer.push(Comment {
+        columns: vec!["0:00:01".to_string(), "1".to_string()],
+        text: "abcd".to_string(),
+    });
+    buffer.push(Comment {
+        columns: vec!["0:00:02".to_string(), "2".to_string()],
+        text: String::new(),
+    });
+    let columns: Vec<usize> = buffer
+        .visible()
+        .map(|(_, line)| line.columns.len())
+        .collect();
+    assert_eq!(columns, [2, 0, 2]);
+}
+
+#[test]
+fn rewraps_on_resize() {
+    let mut buffer = CommentBuffer::new(2, 10);
+    buffer.push("abcd".to_string());
+    buffer.push("ef".to_string());
+    assert_eq!(visible(&buffer), ["ab", "cd", "ef"]);
+
+    buffer.resize(4, 10);
+    assert_eq!(visible(&buffer), ["abcd", "ef"]);
+    buffer.resize(1, 10);
+    assert_eq!(visible(&buffer), ["a", "b", "c", "d", "e", "f"]);
+}
+
+#[test]
+fn resize_keeps_scrolled_position() {
+    let mut buffer = CommentBuffer::new(4, 2);
+    for text in ["一二", "三四", "五六", "七八"] {
+        buffer.push(text.to_string());
+    }
+    buffer.scroll_up(2);
+    assert_eq!(visible(&buffer), ["一二", "三四"]);
+
+    // 幅を狭めても、最下行にあったコメントは最下行のまま
+    buffer.resize(2, 2);
+    assert_eq!(visible(&buffer), ["三", "四"]);
+    assert_eq!(buffer.offset(), 4);
+}
+
+#[test]
+fn paused_view_stays_while_comments_arrive() {
+    let mut buffer = CommentBuffer::new(2, 2);
+    for text in ["a", "b", "c"] {
+        buffer.push(text.to_string());
+    }
+    buffer.set_paused(true);
+    buffer.scroll_up(1);
+    assert_eq!(visible(&buffer), ["a", "b"]);
+
+    // 一時停止中に届いた行の分だけ offset が増え、表示は動かない
+    buffer.push("d".to_string());
+    buffer.push("e".to_string());
+    assert_eq!(visible(&buffer), ["a", "b"]);
+    assert_eq!(buffer.offset(), 3);
+
+    buffer.scroll_down(1);
+    assert_eq!(visible(&buffer), ["b", "c"]);
+    buffer.set_paused(false);
+    assert_eq!(visible(&buffer), ["d", "e"]);
+    assert!(buffer.is_following());
+}
+
+#[test]
+fn resize_clamps_offset() {
+    let mut buffer = CommentBuffer::new(4, 2);
+    for text in ["ab", "cd", "ef", "gh"] {
+        buffer.push(text.to_string());
+    }
+    buffer.scroll_to_top();
+    assert_eq!(buffer.offset(), 2);
+
+    // 高さを増やしたら、さかのぼれる範囲に収める
+    buffer.resize(4, 3);
+    assert_eq!(buffer.offset(), 1);
+    assert_eq!(visible(&buffer), ["ab", "cd", "ef"]);
+
+    // 最新の行を表示していたら、折り返し直しても最新の行のまま
+    buffer.scroll_to_bottom();
+    buffer.resize(1, 3);
+    assert_eq!(buffer.offset(), 0);
+    assert_eq!(visible(&buffer), ["f", "g", "h"]);
+}
+
+#[test]
+fn find_in_wrapped_lines() {
+    let mut buffer = CommentBuffer::new(2, 10);
+    buffer.push("abcd".to_string());
+    buffer.push("xy".to_string());
+
+    // 折り返した 2 行目も 1 行として探す
+    assert_eq!(buffer.find("cd", 0, SearchDirection::Forward), Some(1));
+    assert_eq!(buffer.find("ab", 2, SearchDirection::Backward), Some(0));
+    assert_eq!(buffer.find("xy", 0, SearchDirection::Backward), None);
+    // 折り返しをまたぐ文字列は見つからない
+    assert_eq!(buffer.find("bc", 0, SearchDirection::Forward), None);
+}
+
+#[test]
+fn reveal_scrolls_minimally() {
+    let mut buffer = CommentBuffer::new(2, 2);
+    for text in ["a", "b", "c", "d", "e", "f"] {
+        buffer.push(text.to_string());
+    }
+    buffer.reveal(0);
+    assert_eq!(visible(&buffer), ["a", "b"]);
+    // 表示範囲にある行なら動かさない
+    buffer.reveal(1);
+    assert_eq!(visible(&buffer), ["a", "b"]);
+    buffer.reveal(5);
+    assert_eq!(visible(&buffer), ["e", "f"]);
+    buffer.reveal(3);
+    assert_eq!(visible(&buffer), ["d", "e"]);
+}
+
+#[test]
+fn reveal_dropped_line() {
+    let mut buffer = CommentBuffer::with_scrollback(2, 2, 3);
+    for text in ["a", "b", "c", "d", "e"] {
+        buffer.push(text.to_string());
+    }
+    // 捨てた行は、保持している最も古い行まで戻す
+    buffer.reveal(0);
+    assert_eq!(visible(&buffer), ["c", "d"]);
+    assert_eq!(buffer.bottom_line(), 3);
+}
+
+#[test]
+fn trims_whole_comments() {
+    let mut buffer = CommentBuffer::with_scrollback(2, 1, 3);
+    buffer.push("abcd".to_string());
+    buffer.push("ef".to_string());
+    buffer.push("gh".to_string());
+    let lines: Vec<&str> = buffer
+        .lines()
+        .iter()
+        .map(|line| line.text.as_str())
+        .collect();
+    assert_eq!(lines, ["ef", "gh"]);
+
+    // 狭めて行数が増えたら、古いコメントから捨てる
+    buffer.resize(1, 1);
+    let lines: Vec<&str> = buffer
+        .lines()
+        .iter()
+        .map(|line| line.text.as_str())
+        .collect();
+    assert_eq!(lines, ["g", "h"]);
+}
+
+#[test]
+fn keeps_newest_comment() {
+    let mut buffer = CommentBuffer::with_scrollback(2, 1, 1);
+    // 1 件で上限を超えても、最新のコメントは末尾の行を残す
+    buffer.push("abcd".to_string());
+    assert_eq!(visible(&buffer), ["cd"]);
+    buffer.push("ef".to_string());
+    assert_eq!(visible(&buffer), ["ef"]);
+    buffer.resize(1, 1);
+    assert_eq!(visible(&buffer), ["f"]);
+}
+
+#[test]
+fn renumbers_on_resize() {
+    let mut buffer = CommentBuffer::with_scrollback(2, 2, 2);
+    for text in ["ab", "cd", "ef"] {
+        buffer.push(text.to_string());
+    }
+    let numbers: Vec<usize> = buffer.visible().map(|(number, _)| number).collect();
+    assert_eq!(numbers, [1, 2]);
+
+    // 幅を変えたら、残っている先頭の行を 0 として振り直す
+    buffer.resize(4, 2);
+    let numbers: Vec<usize> = buffer.visible().map(|(number, _)| number).collect();
+    assert_eq!(numbers, [0, 1]);
+    assert_eq!(buffer.bottom_line(), 1);
+}
+
+#[test]
+fn tiny_sizes() {
+    let mut buffer = CommentBuffer::new(0, 0);
+    buffer.push("あい".to_string());
+    assert!(visible(&buffer).is_empty());
+    buffer.page_up();
+    buffer.scroll_to_top();
+
+    buffer.resize(1, 2);
+    // 幅 1 に入らない全角文字はそれだけで 1 行にする
+    assert_eq!(visible(&buffer), ["あ", "い"]);
+    assert_eq!(buffer.width(), 1);
+}
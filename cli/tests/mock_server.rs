@@ -0,0 +1,803 @@
+//! ローカルの [`MockServer`] を相手に、番組ページの取得からメッセージの受信までを通して確かめる
+
+use std::time::Duration;
+
+use base64::prelude::{BASE64_STANDARD, Engine as _};
+use futures::{Stream, StreamExt, pin_mut};
+use ndgr_client::archive::{ArchiveWriter, Recorder};
+use ndgr_client::client::{ClientConfig, NdgrClient};
+use ndgr_client::error::ProgramInfoError;
+use ndgr_client::history::fetch_history;
+use ndgr_client::multi::{MultiWatcher, WatchEvent};
+use ndgr_client::replay::{self, Pacing, ReplayOptions};
+use ndgr_client::resume::StreamResume;
+use ndgr_client::retry::StreamError;
+use ndgr_client::timeshift::{TimeshiftOptions, stream_timeshift};
+use ndgr_client::websocket::{
+    ConnectionState, PostComment, PostCommentError, WebSocketClient, WebSocketEvent,
+};
+use ndgr_client::{
+    StreamItem, StreamOptions, ViewQuery, fetch_program_info, stream_chunked_message,
+    stream_chunked_message_with_options,
+};
+use ndgr_mock_server::{Fixture, MockServer, SEGMENT_SEC, chat, program_ended};
+use prost::Message;
+use protobuf::chat::service::edge::ChunkedMessage;
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+
+const BEGIN_TIME: i64 = 1_700_000_000;
+
+/// 3 セグメントで、2 番目のセグメントは 1 番目の最後と重なっている
+fn fixture() -> Fixture {
+    let at = |sec| BEGIN_TIME + sec;
+    Fixture {
+        segments: vec![
+            vec![chat("m1", 1, "一", at(1)), chat("m2", 2, "二", at(10))],
+            vec![chat("m2", 2, "二", at(10)), chat("m3", 3, "三", at(20))],
+            vec![chat("m4", 4, "四", at(40))],
+        ],
+        ..Fixture::new("lv1", BEGIN_TIME)
+    }
+}
+
+/// `ProgramEnded` が来るまでのメッセージの `meta.id`
+async fn collect_ids(stream: impl Stream<Item = Result<StreamItem, StreamError>>) -> Vec<String> {
+    pin_mut!(stream);
+    let mut ids = Vec::new();
+    let collect = async {
+        while let Some(item) = stream.next().await {
+            match item.unwrap() {
+                StreamItem::Message(message) => ids.push(message_id(&message)),
+                StreamItem::ProgramEnded => return,
+            }
+        }
+        panic!("stream ended without ProgramEnded");
+    };
+    tokio::time::timeout(Duration::from_secs(5), collect)
+        .await
+        .expect("stream timed out");
+    ids
+}
+
+fn message_id(message: &ChunkedMessage) -> String {
+    message.meta.as_ref().unwrap().id.clone()
+}
+
+async fn next_event(events: &mut broadcast::Receiver<WebSocketEvent>) -> WebSocketEvent {
+    tokio::time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("event timed out")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn program_info_from_watch_page() {
+    let server = MockServer::start(fixture()).await.unwrap();
+
+    let info = fetch_program_info(&server.watch_url()).await.unwrap();
+
+    assert_eq!(info.program.nicolive_program_id, "lv1");
+    assert_eq!(info.program.title, "テスト番組");
+    assert_eq!(info.program.begin_time, BEGIN_TIME);
+    assert_eq!(info.social_group.id, "co1");
+    assert!(
+        info.site
+            .relive
+            .web_socket_url
+            .starts_with(&server.base_url().replacen("http", "ws", 1))
+    );
+}
+
+#[tokio::test]
+async fn program_info_errors() {
+    let mut fixture = fixture();
+    fixture.props["userProgramWatch"]["rejectedReasons"] = json!(["notLogin"]);
+    let server = MockServer::start(fixture).await.unwrap();
+
+    let error = fetch_program_info(&server.watch_url()).await.unwrap_err();
+    assert!(matches!(error, ProgramInfoError::LoginRequired), "{error}");
+
+    let error = fetch_program_info(&format!("{}/watch/lv2", server.base_url()))
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ProgramInfoError::HttpStatus(status) if status == 404));
+}
+
+#[tokio::test]
+async fn web_socket_session() {
+    let server = MockServer::start(fixture()).await.unwrap();
+    let info = fetch_program_info(&server.watch_url()).await.unwrap();
+
+    let client = WebSocketClient::new(&info.site.relive.web_socket_url)
+        .await
+        .unwrap();
+    assert_eq!(client.view_uri(), server.view_uri());
+
+    server.ping();
+    server
+        .wait_until(|log| log.received.iter().any(|m| m["type"] == "pong"))
+        .await
+        .unwrap();
+
+    let result = client
+        .post(PostComment::new("こんにちは", 100))
+        .await
+        .unwrap();
+    assert_eq!(result.chat.content, "こんにちは");
+    assert_eq!(result.chat.mail, "184");
+
+    let log = server.log();
+    let post = log
+        .received
+        .iter()
+        .find(|m| m["type"] == "postComment")
+        .unwrap();
+    assert_eq!(post["data"]["text"], "こんにちは");
+    assert_eq!(post["data"]["vpos"], 100);
+    assert_eq!(post["data"]["isAnonymous"], true);
+
+    client.shutdown().await;
+}
+
+/// `CONNECT` を 1 回だけ受け付けるプロキシ。受け取った `Proxy-Authorization` を返す。
+/// `tunnel` が `false` なら応答せずに黙る。
+async fn start_proxy(tunnel: bool) -> (String, tokio::task::JoinHandle<Option<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let task = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut header = Vec::new();
+        while !header.ends_with(b"\r\n\r\n") {
+            let mut byte = [0; 1];
+            stream.read_exact(&mut byte).await.unwrap();
+            header.push(byte[0]);
+        }
+        let header = String::from_utf8(header).unwrap();
+        if !tunnel {
+            std::future::pending::<()>().await;
+        }
+        let target = header.split_whitespace().nth(1).unwrap().to_string();
+        let authorization = header
+            .lines()
+            .find_map(|line| line.strip_prefix("Proxy-Authorization: "))
+            .map(str::to_string);
+
+        let mut upstream = TcpStream::connect(target).await.unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+            .await
+            .unwrap();
+        tokio::spawn(async move {
+            let _ = tokio::io::copy_bidirectional(&mut stream, &mut upstream).await;
+        });
+        authorization
+    });
+    (format!("127.0.0.1:{}", addr.port()), task)
+}
+
+#[tokio::test]
+async fn web_socket_via_proxy() {
+    let server = MockServer::start(fixture()).await.unwrap();
+    let info = fetch_program_info(&server.watch_url()).await.unwrap();
+    let (proxy, authorization) = start_proxy(true).await;
+
+    // `@` と `:` を含む認証情報はパーセントエンコードして URL に書く
+    let client = NdgrClient::builder()
+        .config(ClientConfig {
+            proxy: Some(format!("http://us%40er:pa%3Ass@{proxy}")),
+            ..ClientConfig::default()
+        })
+        .build()
+        .unwrap();
+    let web_socket_client = WebSocketClient::connect(&client, &info.site.relive.web_socket_url)
+        .await
+        .unwrap();
+    assert_eq!(web_socket_client.view_uri(), server.view_uri());
+
+    let expected = format!("Basic {}", BASE64_STANDARD.encode("us@er:pa:ss"));
+    assert_eq!(authorization.await.unwrap(), Some(expected));
+}
+
+#[tokio::test]
+async fn cookie_only_for_nicovideo() {
+    let server = MockServer::start(fixture()).await.unwrap();
+    let client = NdgrClient::builder()
+        .user_session("secret")
+        .build()
+        .unwrap();
+    let info = client
+        .fetch_program_info(&server.watch_url())
+        .await
+        .unwrap();
+    let web_socket_client = WebSocketClient::connect(&client, &info.site.relive.web_socket_url)
+        .await
+        .unwrap();
+    let stream = client
+        .fetch_chunked_entry(&web_socket_client.view_uri(), &ViewQuery::Now)
+        .await;
+    pin_mut!(stream);
+    stream.next().await.unwrap().unwrap();
+    assert!(server.log().cookies.is_empty());
+
+    // nicovideo.jp 宛てには付ける。プロキシに届いたリクエストで確かめる。
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = listener.local_addr().unwrap();
+    let head = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(socket.read_u8().await.unwrap());
+        }
+        socket
+            .write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        String::from_utf8(head).unwrap().to_lowercase()
+    });
+    let client = NdgrClient::builder()
+        .user_session("secret")
+        .config(ClientConfig {
+            proxy: Some(format!("http://{proxy}")),
+            ..ClientConfig::default()
+        })
+        .build()
+        .unwrap();
+    let _ = client
+        .fetch_program_info("http://live.nicovideo.jp/watch/lv1")
+        .await;
+    assert!(head.await.unwrap().contains("cookie: user_session=secret"));
+}
+
+#[tokio::test]
+async fn web_socket_proxy_timeout() {
+    let (proxy, _task) = start_proxy(false).await;
+    let client = NdgrClient::builder()
+        .config(ClientConfig {
+            proxy: Some(format!("http://{proxy}")),
+            connect_timeout: Some(Duration::from_millis(200)),
+            ..ClientConfig::default()
+        })
+        .build()
+        .unwrap();
+
+    let connecting = WebSocketClient::connect(&client, "ws://example.invalid/ws");
+    let result = tokio::time::timeout(Duration::from_secs(5), connecting)
+        .await
+        .expect("connect_timeout was not applied");
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn web_socket_connect_timeout() {
+    // 接続は受け付けるがハンドシェイクに応答しないサーバー
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let client = NdgrClient::builder()
+        .config(ClientConfig {
+            connect_timeout: Some(Duration::from_millis(200)),
+            ..ClientConfig::default()
+        })
+        .build()
+        .unwrap();
+
+    let url = format!("ws://{address}/ws");
+    let connecting = WebSocketClient::connect(&client, &url);
+    let result = tokio::time::timeout(Duration::from_secs(5), connecting)
+        .await
+        .expect("connect_timeout was not applied");
+    assert!(result.is_err());
+    drop(listener);
+}
+
+#[tokio::test]
+async fn keep_seat() {
+    let fixture = Fixture {
+        keep_interval_sec: 1,
+        ..fixture()
+    };
+    let server = MockServer::start(fixture).await.unwrap();
+    let info = fetch_program_info(&server.watch_url()).await.unwrap();
+
+    let client = WebSocketClient::new(&info.site.relive.web_socket_url)
+        .await
+        .unwrap();
+    let mut status = client.subscribe_status();
+
+    server
+        .wait_until(|log| log.received.iter().any(|m| m["type"] == "keepSeat"))
+        .await
+        .unwrap();
+    let status = tokio::time::timeout(
+        Duration::from_secs(5),
+        status.wait_for(|status| status.last_keep_seat.is_some()),
+    )
+    .await
+    .expect("status timed out")
+    .unwrap();
+    assert_eq!(status.keep_interval_sec, 1);
+    assert_eq!(status.connection, ConnectionState::Connected);
+}
+
+#[tokio::test]
+async fn rejected_comment() {
+    let fixture = Fixture {
+        post_error: Some("COMMENT_LOCKED".to_string()),
+        ..fixture()
+    };
+    let server = MockServer::start(fixture).await.unwrap();
+    let info = fetch_program_info(&server.watch_url()).await.unwrap();
+    let client = WebSocketClient::new(&info.site.relive.web_socket_url)
+        .await
+        .unwrap();
+
+    let error = client
+        .post(PostComment::new("こんにちは", 100))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<PostCommentError>().unwrap().code,
+        "COMMENT_LOCKED"
+    );
+}
+
+#[tokio::test]
+async fn unrelated_error_while_posting() {
+    let fixture = Fixture {
+        unrelated_error: Some("INTERNAL_SERVERERROR".to_string()),
+        ..fixture()
+    };
+    let server = MockServer::start(fixture).await.unwrap();
+    let info = fetch_program_info(&server.watch_url()).await.unwrap();
+    let client = WebSocketClient::new(&info.site.relive.web_socket_url)
+        .await
+        .unwrap();
+    let mut events = client.subscribe_events();
+
+    // 投稿の前に届いた関係ないエラーで投稿は失敗しない
+    let result = client
+        .post(PostComment::new("こんにちは", 100))
+        .await
+        .unwrap();
+    assert_eq!(result.chat.content, "こんにちは");
+    assert_eq!(
+        next_event(&mut events).await,
+        WebSocketEvent::Error {
+            message: "server error: INTERNAL_SERVERERROR".to_string()
+        }
+    );
+}
+
+#[tokio::test]
+async fn reconnect_and_disconnect() {
+    let server = MockServer::start(fixture()).await.unwrap();
+    let info = fetch_program_info(&server.watch_url()).await.unwrap();
+    let client = WebSocketClient::new(&info.site.relive.web_socket_url)
+        .await
+        .unwrap();
+    let mut events = client.subscribe_events();
+    let status = client.subscribe_status();
+
+    server.reconnect("second", 0);
+    assert_eq!(
+        next_event(&mut events).await,
+        WebSocketEvent::Reconnecting { wait_time_sec: 0 }
+    );
+    let log = server
+        .wait_until(|log| log.connections.len() == 2)
+        .await
+        .unwrap();
+    assert_eq!(
+        log.connections[0].audience_token.as_deref(),
+        Some("initial")
+    );
+    assert!(!log.connections[0].reconnect);
+    assert_eq!(log.connections[1].audience_token.as_deref(), Some("second"));
+    assert!(log.connections[1].reconnect);
+
+    server.disconnect("END_PROGRAM");
+    assert_eq!(
+        next_event(&mut events).await,
+        WebSocketEvent::Disconnected {
+            reason: "END_PROGRAM".to_string()
+        }
+    );
+    assert_eq!(next_event(&mut events).await, WebSocketEvent::Closed);
+    assert_eq!(
+        status.borrow().connection,
+        ConnectionState::Disconnected {
+            reason: "END_PROGRAM".to_string()
+        }
+    );
+}
+
+#[tokio::test]
+async fn stream_follows_segments() {
+    let server = MockServer::start(fixture()).await.unwrap();
+
+    let ids = collect_ids(stream_chunked_message(&server.view_uri()).await).await;
+
+    // 重なった m2 は 1 度だけ流れる
+    assert_eq!(ids, ["m1", "m2", "m3", "m4"]);
+    let log = server.log();
+    assert!(log.requests.contains(&"/view?at=now".to_string()));
+    assert!(
+        log.requests
+            .contains(&format!("/view?at={}", BEGIN_TIME + SEGMENT_SEC))
+    );
+}
+
+#[tokio::test]
+async fn stream_without_dedup() {
+    let server = MockServer::start(fixture()).await.unwrap();
+    let options = StreamOptions {
+        dedup: None,
+        ..StreamOptions::default()
+    };
+
+    let view_uri = server.view_uri();
+    let stream = stream_chunked_message_with_options(&view_uri, options);
+    let ids = collect_ids(stream.await).await;
+
+    assert_eq!(ids, ["m1", "m2", "m2", "m3", "m4"]);
+}
+
+#[tokio::test]
+async fn stream_skips_seen_history() {
+    let server = MockServer::start(fixture()).await.unwrap();
+    // 過去コメントとして表示済みのメッセージは最新のセグメントから流さない
+    let options = StreamOptions {
+        seen_ids: vec!["m1".to_string(), "m2".to_string()],
+        ..StreamOptions::default()
+    };
+
+    let view_uri = server.view_uri();
+    let stream = stream_chunked_message_with_options(&view_uri, options);
+    let ids = collect_ids(stream.await).await;
+
+    assert_eq!(ids, ["m3", "m4"]);
+}
+
+#[tokio::test]
+async fn stream_resumes_after_reopen() {
+    let server = MockServer::start(fixture()).await.unwrap();
+    let view_uri = server.view_uri();
+
+    // m2 まで受け取ったところで張り直す
+    let mut resume = StreamResume::new();
+    let stream = stream_chunked_message(&view_uri).await;
+    pin_mut!(stream);
+    while let Some(item) = stream.next().await {
+        if let StreamItem::Message(message) = item.unwrap() {
+            resume.record(&message);
+            if message_id(&message) == "m2" {
+                break;
+            }
+        }
+    }
+    assert_eq!(resume.last_at(), Some((BEGIN_TIME + 10) as f64));
+
+    let options = resume.resume(StreamOptions::default());
+    let stream = stream_chunked_message_with_options(&view_uri, options);
+    let ids = collect_ids(stream.await).await;
+    assert_eq!(ids, ["m3", "m4"]);
+}
+
+#[tokio::test]
+async fn stream_skips_corrupt_frame() {
+    let mut body = Vec::new();
+    chat("m1", 1, "一", BEGIN_TIME + 1)
+        .encode_length_delimited(&mut body)
+        .unwrap();
+    // 長さは正しいが中身が protobuf として読めないフレーム
+    body.extend_from_slice(&[3, 0xff, 0xff, 0xff]);
+    chat("m2", 2, "二", BEGIN_TIME + 2)
+        .encode_length_delimited(&mut body)
+        .unwrap();
+    let mut fixture = Fixture {
+        segments: vec![Vec::new()],
+        ..fixture()
+    };
+    fixture.raw.insert("segment/0".to_string(), body);
+    let server = MockServer::start(fixture).await.unwrap();
+
+    let stream = stream_chunked_message(&server.view_uri()).await;
+    pin_mut!(stream);
+    let mut ids = Vec::new();
+    let mut skipped = 0;
+    while let Some(item) = stream.next().await {
+        match item {
+            Ok(StreamItem::Message(message)) => ids.push(message_id(&message)),
+            Ok(StreamItem::ProgramEnded) => break,
+            Err(e) => {
+                assert_eq!(e.attempt, 0);
+                assert!(e.retrying);
+                skipped += 1;
+            }
+        }
+    }
+
+    // 壊れたフレームの後も同じセグメントを読み続け、view を取り直さない
+    assert_eq!(ids, ["m1", "m2"]);
+    assert_eq!(skipped, 1);
+    let views = server
+        .log()
+        .requests
+        .iter()
+        .filter(|r| r.starts_with("/view"))
+        .count();
+    assert_eq!(views, 1);
+}
+
+#[tokio::test]
+async fn stream_retries_unavailable_view() {
+    let fixture = Fixture {
+        view_failures: 1,
+        ..fixture()
+    };
+    let server = MockServer::start(fixture).await.unwrap();
+
+    let stream = stream_chunked_message(&server.view_uri()).await;
+    pin_mut!(stream);
+    // 503 は番組終了ではなく、再試行するエラーになる
+    let error = match stream.next().await.unwrap() {
+        Err(e) => e,
+        Ok(item) => panic!("expected an error, got {item:?}"),
+    };
+    assert_eq!(error.attempt, 1);
+    assert!(error.retrying);
+
+    let ids = collect_ids(stream).await;
+    assert_eq!(ids, ["m1", "m2", "m3", "m4"]);
+}
+
+#[tokio::test]
+async fn stream_stops_at_program_end() {
+    let mut fixture = fixture();
+    fixture.segments[1].push(program_ended("end", BEGIN_TIME + 30));
+    let server = MockServer::start(fixture).await.unwrap();
+
+    let ids = collect_ids(stream_chunked_message(&server.view_uri()).await).await;
+
+    assert_eq!(ids, ["m1", "m2", "m3", "end"]);
+}
+
+#[tokio::test]
+async fn timeshift_from_position() {
+    let server = MockServer::start(fixture()).await.unwrap();
+    let options = TimeshiftOptions {
+        speed: f64::INFINITY,
+        ..TimeshiftOptions::new(BEGIN_TIME + 15)
+    };
+
+    let ids = collect_ids(stream_timeshift(&server.view_uri(), options).await).await;
+
+    // 再生位置を含む先頭のセグメントから取得し、それより前の m1 / m2 は飛ばす
+    assert_eq!(ids, ["m3", "m4"]);
+}
+
+#[tokio::test]
+async fn timeshift_until_end_at() {
+    let server = MockServer::start(fixture()).await.unwrap();
+    let options = TimeshiftOptions {
+        end_at: Some(BEGIN_TIME + 25),
+        speed: f64::INFINITY,
+        ..TimeshiftOptions::new(BEGIN_TIME)
+    };
+
+    // `end_at` を過ぎたら ProgramEnded で終わる
+    let ids = collect_ids(stream_timeshift(&server.view_uri(), options).await).await;
+    assert_eq!(ids, ["m1", "m2", "m3"]);
+}
+
+#[tokio::test]
+async fn timeshift_rejects_invalid_speed() {
+    let server = MockServer::start(fixture()).await.unwrap();
+    for speed in [0.0, -1.0, f64::NAN] {
+        let options = TimeshiftOptions {
+            speed,
+            ..TimeshiftOptions::new(BEGIN_TIME)
+        };
+        let stream = stream_timeshift(&server.view_uri(), options).await;
+        pin_mut!(stream);
+        let error = stream.next().await.unwrap().unwrap_err();
+        assert!(!error.retrying);
+        assert!(stream.next().await.is_none());
+    }
+    assert!(
+        server
+            .log()
+            .requests
+            .iter()
+            .all(|path| !path.starts_with("/view"))
+    );
+}
+
+#[tokio::test]
+async fn history_and_snapshot() {
+    let fixture = Fixture {
+        history: vec![
+            chat("h1", 1, "過去一", BEGIN_TIME - 20),
+            chat("h2", 2, "過去二", BEGIN_TIME - 10),
+            chat("h3", 3, "過去三", BEGIN_TIME - 5),
+        ],
+        snapshot: vec![program_ended("snapshot", BEGIN_TIME)],
+        ..fixture()
+    };
+    let server = MockServer::start(fixture).await.unwrap();
+
+    let history = fetch_history(&server.view_uri(), 2).await.unwrap();
+
+    let ids: Vec<String> = history.messages.iter().map(message_id).collect();
+    assert_eq!(ids, ["h2", "h3"]);
+    let ids: Vec<String> = history.snapshot.iter().map(message_id).collect();
+    assert_eq!(ids, ["snapshot"]);
+}
+
+#[tokio::test]
+async fn record_and_replay() {
+    let server = MockServer::start(fixture()).await.unwrap();
+    let path = std::env::temp_dir().join(format!("ndgr-mock-{}.ndgr", std::process::id()));
+
+    let recorder = Recorder::new(ArchiveWriter::create(&path, Some(3)).unwrap());
+    let client = NdgrClient::default().with_recorder(recorder.clone());
+    let options = StreamOptions {
+        client,
+        ..StreamOptions::default()
+    };
+    let view_uri = server.view_uri();
+    let stream = stream_chunked_message_with_options(&view_uri, options);
+    let live = collect_ids(stream.await).await;
+    recorder.finish().await.unwrap();
+
+    let options = ReplayOptions {
+        pacing: Pacing::AsFastAsPossible,
+        ..ReplayOptions::default()
+    };
+    let replayed = collect_ids(replay::open_with_options(&path, options).await.unwrap()).await;
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(replayed, live);
+}
+
+/// 先頭のマジックナンバーだけ受け付けて、それ以降は失敗する出力先
+struct FullDisk {
+    written: usize,
+}
+
+impl std::io::Write for FullDisk {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.written > 0 {
+            return Err(std::io::Error::other("disk full"));
+        }
+        self.written += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn record_reports_write_error() {
+    let server = MockServer::start(fixture()).await.unwrap();
+    let recorder = Recorder::new(ArchiveWriter::new(FullDisk { written: 0 }).unwrap());
+    let options = StreamOptions {
+        client: NdgrClient::default().with_recorder(recorder.clone()),
+        ..StreamOptions::default()
+    };
+
+    // 書き込みに失敗しても受信は続け、記録を終えるときにエラーを返す
+    let view_uri = server.view_uri();
+    let ids = collect_ids(stream_chunked_message_with_options(&view_uri, options).await).await;
+    assert_eq!(ids, ["m1", "m2", "m3", "m4"]);
+    let error = recorder.finish().await.unwrap_err();
+    assert!(error.to_string().contains("disk full"), "{error}");
+
+    // 閉じた後にもう一度呼んでもエラーにしない
+    recorder.finish().await.unwrap();
+}
+
+#[tokio::test]
+async fn replay_recorded_history() {
+    let fixture = Fixture {
+        history: vec![
+            chat("h1", 1, "過去一", BEGIN_TIME - 20),
+            chat("h2", 2, "過去二", BEGIN_TIME - 10),
+        ],
+        ..fixture()
+    };
+    let server = MockServer::start(fixture).await.unwrap();
+    let path = std::env::temp_dir().join(format!("ndgr-history-{}.ndgr", std::process::id()));
+
+    let recorder = Recorder::new(ArchiveWriter::create(&path, None).unwrap());
+    let client = NdgrClient::default().with_recorder(recorder.clone());
+    let view_uri = server.view_uri();
+    client.fetch_history(&view_uri, 10).await.unwrap();
+    let options = StreamOptions {
+        client,
+        ..StreamOptions::default()
+    };
+    collect_ids(stream_chunked_message_with_options(&view_uri, options).await).await;
+    recorder.finish().await.unwrap();
+
+    // 過去ログはセグメントより先に流す
+    let options = ReplayOptions {
+        pacing: Pacing::AsFastAsPossible,
+        ..ReplayOptions::default()
+    };
+    let replayed = collect_ids(replay::open_with_options(&path, options).await.unwrap()).await;
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(replayed, ["h1", "h2", "m1", "m2", "m3", "m4"]);
+}
+
+#[tokio::test]
+async fn multi_watcher_tags_events() {
+    let first = MockServer::start(fixture()).await.unwrap();
+    let second = MockServer::start(Fixture {
+        segments: vec![vec![chat("n1", 1, "別番組", BEGIN_TIME + 1)]],
+        ..Fixture::new("lv2", BEGIN_TIME)
+    })
+    .await
+    .unwrap();
+
+    let mut watcher = MultiWatcher::new(NdgrClient::default());
+    watcher.add(&first.watch_url()).await.unwrap();
+    watcher.add(&second.watch_url()).await.unwrap();
+    assert!(watcher.add(&first.watch_url()).await.is_err());
+    let ids: Vec<&str> = watcher
+        .programs()
+        .map(|info| info.program.nicolive_program_id.as_str())
+        .collect();
+    assert_eq!(ids, ["lv1", "lv2"]);
+
+    let mut messages = vec![Vec::new(), Vec::new()];
+    let mut ended = 0;
+    let collect = async {
+        while ended < 2 {
+            let event = watcher.next_event().await;
+            let index = if event.program_id == "lv1" { 0 } else { 1 };
+            match event.event {
+                WatchEvent::Message(message) => messages[index].push(message_id(&message)),
+                WatchEvent::ProgramEnded => ended += 1,
+                WatchEvent::StreamError(e) => panic!("{e}"),
+                WatchEvent::WebSocket(_) => {}
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), collect)
+        .await
+        .expect("events timed out");
+    assert_eq!(messages, [vec!["m1", "m2", "m3", "m4"], vec!["n1"]]);
+
+    let result = watcher
+        .poster("lv2")
+        .unwrap()
+        .post(PostComment::new("こんにちは", 0))
+        .await
+        .unwrap();
+    assert_eq!(result.chat.content, "こんにちは");
+    assert!(
+        second
+            .log()
+            .received
+            .iter()
+            .any(|m| m["type"] == "postComment")
+    );
+    assert!(
+        !first
+            .log()
+            .received
+            .iter()
+            .any(|m| m["type"] == "postComment")
+    );
+
+    assert!(watcher.remove("lv1").await);
+    assert!(!watcher.remove("lv1").await);
+    assert!(watcher.info("lv1").is_none());
+    watcher.shutdown().await;
+}
@@ -0,0 +1,53 @@
+//! [`Message::FIELDS`] が JSON に現れるキーをすべて含んでいるか確かめる
+
+use ndgr_client::model::{
+    Chat, Event, Gift, Message, Nicoad, Notification, NotificationKind, Signal, StateChange,
+};
+
+#[test]
+fn fields_cover_json_keys() {
+    let events = [
+        Event::Chat(Chat {
+            no: 1,
+            vpos: 100,
+            content: "こんにちは".to_string(),
+            name: Some("名前".to_string()),
+            raw_user_id: Some(1),
+            hashed_user_id: Some("a:b".to_string()),
+            premium: true,
+            overflowed: false,
+        }),
+        Event::Gift(Gift {
+            item_id: "item".to_string(),
+            advertiser_user_id: Some(1),
+            advertiser_name: "名前".to_string(),
+            item_name: "ギフト".to_string(),
+            point: 100,
+            message: String::new(),
+        }),
+        Event::Nicoad(Nicoad {
+            content: "広告".to_string(),
+        }),
+        Event::Notification(Notification {
+            kind: NotificationKind::Emotion,
+            content: "通知".to_string(),
+        }),
+        Event::StateChange(StateChange {
+            comment_locked: Some(true),
+            ..StateChange::default()
+        }),
+        Event::Signal(Signal::Flushed),
+    ];
+
+    for event in events {
+        let message = Message {
+            id: "m1".to_string(),
+            at: Some(1),
+            event,
+        };
+        let value = serde_json::to_value(&message).unwrap();
+        for key in value.as_object().unwrap().keys() {
+            assert!(Message::FIELDS.contains(&key.as_str()), "{key}");
+        }
+    }
+}
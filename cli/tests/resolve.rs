@@ -0,0 +1,94 @@
+//! [`ProgramRef::parse`] が受け付ける ID と URL の書き方を確かめる
+
+use ndgr_client::error::ResolveError;
+use ndgr_client::resolve::{ProgramRef, resolve_watch_url};
+
+fn program(id: &str) -> Option<ProgramRef> {
+    Some(ProgramRef::Program(id.to_string()))
+}
+
+fn social_group(id: &str) -> Option<ProgramRef> {
+    Some(ProgramRef::SocialGroup(id.to_string()))
+}
+
+#[test]
+fn parses_ids() {
+    let cases = [
+        ("lv123", program("lv123")),
+        ("co456", social_group("co456")),
+        ("ch789", social_group("ch789")),
+        // 大文字と前後の空白は揃える
+        ("LV123", program("lv123")),
+        ("  ch789\n", social_group("ch789")),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(ProgramRef::parse(input), expected, "{input:?}");
+    }
+}
+
+#[test]
+fn parses_urls() {
+    let cases = [
+        ("https://live.nicovideo.jp/watch/lv123", program("lv123")),
+        (
+            "https://live.nicovideo.jp/watch/lv123?ref=top",
+            program("lv123"),
+        ),
+        (
+            "https://live.nicovideo.jp/watch/lv123#comment",
+            program("lv123"),
+        ),
+        (
+            "https://live.nicovideo.jp/watch/lv123/?a=1&b=2#c",
+            program("lv123"),
+        ),
+        ("live.nicovideo.jp/watch/co456", social_group("co456")),
+        ("http://live2.nicovideo.jp/watch/lv123", program("lv123")),
+        (
+            "https://sp.live.nicovideo.jp/watch/ch789?ref=sp",
+            social_group("ch789"),
+        ),
+        ("https://nico.ms/lv123?from=share", program("lv123")),
+        ("nico.ms/co456", social_group("co456")),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(ProgramRef::parse(input), expected, "{input:?}");
+    }
+}
+
+#[test]
+fn rejects_invalid_input() {
+    let cases = [
+        "",
+        "lv",
+        "lv12a",
+        "sm9",
+        "123",
+        "https://live.nicovideo.jp/watch/",
+        "https://live.nicovideo.jp/lv123",
+        "https://live.nicovideo.jp/watch/sm9",
+        "https://example.com/watch/lv123",
+        "https://nico.ms/",
+        "not a url",
+    ];
+    for input in cases {
+        assert_eq!(ProgramRef::parse(input), None, "{input:?}");
+    }
+}
+
+#[tokio::test]
+async fn resolve_rejects_invalid_input() {
+    // 読み取れない入力はページを取得する前にエラーにする
+    for input in ["", "sm9", "https://example.com/watch/lv123"] {
+        let error = resolve_watch_url(input).await.unwrap_err();
+        assert!(
+            matches!(&error, ResolveError::InvalidInput(rejected) if rejected == input),
+            "{input:?}: {error}"
+        );
+    }
+
+    let url = resolve_watch_url("https://nico.ms/lv123?from=share")
+        .await
+        .unwrap();
+    assert_eq!(url, "https://live.nicovideo.jp/watch/lv123");
+}
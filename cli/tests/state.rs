@@ -0,0 +1,116 @@
+//! 状態のメッセージを [`StateTracker`] に積み上げて、差分だけが反映されるか確かめる
+
+use ndgr_client::state::StateTracker;
+use protobuf::chat::data::{
+    CommentLock, Enquete, NicoliveState, ProgramStatus, Statistics, comment_lock, enquete,
+    program_status,
+};
+use protobuf::chat::service::edge::ChunkedMessage;
+use protobuf::chat::service::edge::chunked_message::{Meta, Payload};
+
+fn state_message(id: &str, state: NicoliveState) -> ChunkedMessage {
+    ChunkedMessage {
+        meta: Some(Meta {
+            id: id.to_string(),
+            at: None,
+            origin: None,
+        }),
+        payload: Some(Payload::State(state)),
+    }
+}
+
+fn statistics(viewers: Option<i64>, comments: Option<i64>) -> NicoliveState {
+    NicoliveState {
+        statistics: Some(Statistics {
+            viewers,
+            comments,
+            ad_points: None,
+            gift_points: None,
+        }),
+        ..NicoliveState::default()
+    }
+}
+
+fn enquete(status: enquete::Status) -> NicoliveState {
+    NicoliveState {
+        enquete: Some(Enquete {
+            question: "好きな色は?".to_string(),
+            choices: Vec::new(),
+            status: status as i32,
+        }),
+        ..NicoliveState::default()
+    }
+}
+
+#[test]
+fn keeps_fields_missing_from_partial_updates() {
+    let mut tracker = StateTracker::new();
+    assert!(tracker.apply_message(&state_message("s1", statistics(Some(10), Some(5)))));
+
+    // 来場者数だけの差分では、コメント数は前の値のまま
+    assert!(tracker.apply_message(&state_message("s2", statistics(Some(12), None))));
+    let state = tracker.state();
+    assert_eq!(state.statistics.viewers, Some(12));
+    assert_eq!(state.statistics.comments, Some(5));
+
+    // 統計を含まない差分では統計を変えない
+    let locked = NicoliveState {
+        comment_lock: Some(CommentLock {
+            status: comment_lock::Status::Locked as i32,
+        }),
+        ..NicoliveState::default()
+    };
+    assert!(tracker.apply_message(&state_message("s3", locked)));
+    let state = tracker.state();
+    assert!(state.comment_locked);
+    assert_eq!(state.statistics.viewers, Some(12));
+
+    // 同じ値の差分は変化として数えない
+    assert!(!tracker.apply_message(&state_message("s4", statistics(Some(12), None))));
+}
+
+#[test]
+fn closes_enquete() {
+    let mut tracker = StateTracker::new();
+    assert!(tracker.apply_message(&state_message("s1", enquete(enquete::Status::Poll))));
+    assert!(tracker.state().enquete.is_some());
+    assert!(tracker.apply_message(&state_message("s2", enquete(enquete::Status::Closed))));
+    assert_eq!(tracker.state().enquete, None);
+}
+
+#[test]
+fn ended_state_persists() {
+    let mut tracker = StateTracker::new();
+    let ended = NicoliveState {
+        program_status: Some(ProgramStatus {
+            state: program_status::State::Ended as i32,
+        }),
+        ..NicoliveState::default()
+    };
+    assert!(tracker.apply_message(&state_message("s1", ended.clone())));
+    assert!(tracker.state().ended);
+    assert!(!tracker.apply_message(&state_message("s2", ended)));
+
+    // 終了後の差分で終了が取り消されることはない
+    let unknown = NicoliveState {
+        program_status: Some(ProgramStatus {
+            state: program_status::State::Unknown as i32,
+        }),
+        ..NicoliveState::default()
+    };
+    assert!(!tracker.apply_message(&state_message("s3", unknown)));
+    assert!(tracker.apply_message(&state_message("s4", statistics(Some(1), None))));
+    assert!(tracker.state().ended);
+}
+
+#[test]
+fn ignores_other_messages() {
+    let mut tracker = StateTracker::new();
+    assert!(!tracker.apply_message(&state_message("s1", NicoliveState::default())));
+    let empty = ChunkedMessage {
+        meta: None,
+        payload: None,
+    };
+    assert!(!tracker.apply_message(&empty));
+    assert_eq!(tracker.state(), &Default::default());
+}
@@ -0,0 +1,531 @@
+//! ネットワークに出ずに ndgr-client を動かすための、NDGR まわりのサーバーの偽物。
+//!
+//! 1 つのローカルポートで次のものを返す。
+//!
+//! - `/watch/{id}`: `#embedded-data` を埋め込んだ番組ページ
+//! - `/ws`: `startWatching` に `seat` / `messageServer` を返し、`keepSeat` / `pong` /
+//!   `postComment` を受け付ける WebSocket。[`MockServer::send`] で任意のメッセージを流せる。
+//! - `/view`: [`Fixture::segments`] を `at` で引く長さ付き `ChunkedEntry` のストリーム
+//! - `/segment/{index}`, `/snapshot`: 長さ付き `ChunkedMessage` のストリーム
+//! - `/backward`: [`Fixture::history`] を入れた `PackedSegment`
+//! - `/raw/{name}`: [`Fixture::raw`] のバイト列
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::Router;
+use axum::extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::http::{StatusCode, header};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use prost::Message;
+use prost_types::Timestamp;
+use protobuf::chat::data::{Chat, NicoliveMessage, NicoliveState, ProgramStatus, nicolive_message};
+use protobuf::chat::service::edge::chunked_entry::{Entry, ReadyForNext};
+use protobuf::chat::service::edge::chunked_message::{Meta, Payload};
+use protobuf::chat::service::edge::packed_segment::{Next, StateSnapshot};
+use protobuf::chat::service::edge::{
+    BackwardSegment, ChunkedEntry, ChunkedMessage, MessageSegment, PackedSegment,
+};
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+use tokio::sync::{Notify, broadcast};
+use tokio::task::JoinHandle;
+
+/// 1 セグメントが受け持つ秒数
+pub const SEGMENT_SEC: i64 = 16;
+
+/// サーバーが返す番組の中身
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    /// `#embedded-data` の `data-props`。
+    /// `site.relive.webSocketUrl` がなければこのサーバーの `/ws` を入れる。
+    pub props: Value,
+    /// `i` 番目のセグメントは `begin_time + i * SEGMENT_SEC` から始まる。
+    /// `at=now` は先頭のセグメントを返し、最後のセグメントの後には `next` を返さない。
+    pub segments: Vec<Vec<ChunkedMessage>>,
+    /// view が `backward` で指す過去のメッセージ。空なら `backward` を返さない。
+    pub history: Vec<ChunkedMessage>,
+    /// `backward` の `snapshot` で返す状態
+    pub snapshot: Vec<ChunkedMessage>,
+    /// `seat` で返す座席維持の間隔
+    pub keep_interval_sec: i64,
+    /// `Some` なら `postComment` にこのコードの `error` を返す
+    pub post_error: Option<String>,
+    /// `Some` なら `postComment` への応答の前に、投稿と関係ないこのコードの `error` を送る
+    pub unrelated_error: Option<String>,
+    /// 最初のこの回数の `/view` には 503 を返す
+    pub view_failures: usize,
+    /// `/raw/{name}` でそのまま返すバイト列 (壊れたストリームなど)。
+    /// `segment/{index}` という名前があれば `/segment/{index}` でもこちらを返す。
+    pub raw: HashMap<String, Vec<u8>>,
+}
+
+impl Fixture {
+    /// 放送中・視聴可能な番組 `program_id` で、メッセージは空
+    pub fn new(program_id: &str, begin_time: i64) -> Self {
+        Self {
+            props: json!({
+                "site": { "relive": {} },
+                "program": {
+                    "nicoliveProgramId": program_id,
+                    "title": "テスト番組",
+                    "status": "ON_AIR",
+                    "providerType": "community",
+                    "openTime": begin_time,
+                    "beginTime": begin_time,
+                    "vposBaseTime": begin_time,
+                    "endTime": begin_time + 3600,
+                    "scheduledEndTime": begin_time + 3600,
+                },
+                "socialGroup": { "id": "co1", "name": "テストコミュニティ" },
+                "user": { "isLoggedIn": false },
+                "userProgramWatch": { "rejectedReasons": [] },
+            }),
+            segments: Vec::new(),
+            history: Vec::new(),
+            snapshot: Vec::new(),
+            keep_interval_sec: 30,
+            post_error: None,
+            unrelated_error: None,
+            view_failures: 0,
+            raw: HashMap::new(),
+        }
+    }
+
+    fn begin_time(&self) -> i64 {
+        self.props["program"]["beginTime"]
+            .as_i64()
+            .unwrap_or_default()
+    }
+}
+
+/// サーバーが受け取ったもの
+#[derive(Debug, Clone, Default)]
+pub struct Log {
+    /// HTTP リクエストのパスとクエリ (WebSocket を含む)
+    pub requests: Vec<String>,
+    /// 受け取った `Cookie` ヘッダー (WebSocket を含む)
+    pub cookies: Vec<String>,
+    /// 張られた WebSocket 接続
+    pub connections: Vec<Connection>,
+    /// WebSocket で受け取った JSON (全接続分を受け取った順に)
+    pub received: Vec<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    /// URL の `audience_token`
+    pub audience_token: Option<String>,
+    /// `startWatching` の `reconnect`
+    pub reconnect: bool,
+}
+
+struct Shared {
+    fixture: Fixture,
+    base_url: String,
+    log: Mutex<Log>,
+    log_changed: Notify,
+    /// これまでに受けた `/view` の数
+    views: AtomicUsize,
+    /// [`MockServer::send`] で接続中の WebSocket に流すメッセージ
+    push: broadcast::Sender<Value>,
+}
+
+impl Shared {
+    fn update_log(&self, f: impl FnOnce(&mut Log)) {
+        f(&mut self.log.lock().unwrap());
+        self.log_changed.notify_waiters();
+    }
+}
+
+/// drop すると止まる
+pub struct MockServer {
+    shared: Arc<Shared>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// `127.0.0.1` の空いているポートで起動する
+    pub async fn start(fixture: Fixture) -> Result<Self> {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).await?;
+        let base_url = format!("http://{}", listener.local_addr()?);
+        let (push, _) = broadcast::channel(16);
+        let shared = Arc::new(Shared {
+            fixture,
+            base_url,
+            log: Mutex::new(Log::default()),
+            log_changed: Notify::new(),
+            views: AtomicUsize::new(0),
+            push,
+        });
+
+        let app = Router::new()
+            .route("/watch/{id}", get(watch_page))
+            .route("/ws", get(web_socket))
+            .route("/view", get(view))
+            .route("/segment/{index}", get(segment))
+            .route("/snapshot", get(snapshot))
+            .route("/backward", get(backward))
+            .route("/raw/{name}", get(raw))
+            .layer(axum::middleware::from_fn_with_state(
+                shared.clone(),
+                log_request,
+            ))
+            .with_state(shared.clone());
+        let task = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        Ok(Self { shared, task })
+    }
+
+    /// `http://127.0.0.1:<port>`
+    pub fn base_url(&self) -> &str {
+        &self.shared.base_url
+    }
+
+    /// 番組ページの URL
+    pub fn watch_url(&self) -> String {
+        let id = self.shared.fixture.props["program"]["nicoliveProgramId"]
+            .as_str()
+            .unwrap_or("lv1");
+        format!("{}/watch/{id}", self.base_url())
+    }
+
+    /// `messageServer` で返す view URI
+    pub fn view_uri(&self) -> String {
+        format!("{}/view", self.base_url())
+    }
+
+    /// 接続中のすべての WebSocket に `message` を送る
+    pub fn send(&self, message: Value) {
+        let _ = self.shared.push.send(message);
+    }
+
+    pub fn ping(&self) {
+        self.send(json!({ "type": "ping" }));
+    }
+
+    /// `wait_time_sec` 秒後に `audience_token` で繋ぎ直すよう指示する
+    pub fn reconnect(&self, audience_token: &str, wait_time_sec: i64) {
+        self.send(json!({
+            "type": "reconnect",
+            "data": { "audienceToken": audience_token, "waitTimeSec": wait_time_sec },
+        }));
+    }
+
+    pub fn disconnect(&self, reason: &str) {
+        self.send(json!({ "type": "disconnect", "data": { "reason": reason } }));
+    }
+
+    /// これまでに受け取ったもの
+    pub fn log(&self) -> Log {
+        self.shared.log.lock().unwrap().clone()
+    }
+
+    /// `condition` を満たすまで待つ。5 秒経っても満たさなければエラー
+    pub async fn wait_until(&self, mut condition: impl FnMut(&Log) -> bool) -> Result<Log> {
+        let wait = async {
+            loop {
+                let changed = self.shared.log_changed.notified();
+                {
+                    let log = self.shared.log.lock().unwrap();
+                    if condition(&log) {
+                        return log.clone();
+                    }
+                }
+                changed.await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), wait)
+            .await
+            .map_err(|_| anyhow::anyhow!("timed out waiting for the mock server"))
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn log_request(
+    State(shared): State<Arc<Shared>>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let uri = request.uri();
+    let path = uri
+        .path_and_query()
+        .map_or(uri.path(), |path| path.as_str())
+        .to_string();
+    let cookie = request
+        .headers()
+        .get(header::COOKIE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    shared.update_log(|log| {
+        log.requests.push(path);
+        log.cookies.extend(cookie);
+    });
+    next.run(request).await
+}
+
+async fn watch_page(State(shared): State<Arc<Shared>>, Path(id): Path<String>) -> Response {
+    let mut props = shared.fixture.props.clone();
+    if props["program"]["nicoliveProgramId"] != id.as_str() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if props["site"]["relive"]["webSocketUrl"].is_null() {
+        let ws_url = shared.base_url.replacen("http://", "ws://", 1);
+        props["site"]["relive"]["webSocketUrl"] =
+            format!("{ws_url}/ws?audience_token=initial").into();
+    }
+
+    let props = html_escape(&props.to_string());
+    Html(format!(
+        "<!DOCTYPE html><html><head><title>{id}</title></head><body>\
+         <script id=\"embedded-data\" data-props=\"{props}\"></script></body></html>"
+    ))
+    .into_response()
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+async fn web_socket(
+    State(shared): State<Arc<Shared>>,
+    Query(mut query): Query<HashMap<String, String>>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let audience_token = query.remove("audience_token");
+    upgrade.on_upgrade(move |socket| async move {
+        let _ = serve_web_socket(shared, socket, audience_token).await;
+    })
+}
+
+async fn serve_web_socket(
+    shared: Arc<Shared>,
+    mut socket: WebSocket,
+    audience_token: Option<String>,
+) -> Result<()> {
+    let mut push = shared.push.subscribe();
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => {
+                let Some(message) = message else {
+                    return Ok(());
+                };
+                let WsMessage::Text(text) = message? else {
+                    continue;
+                };
+                let message: Value = serde_json::from_str(&text)?;
+                shared.update_log(|log| log.received.push(message.clone()));
+
+                for reply in replies(&shared, &message, &audience_token) {
+                    socket.send(WsMessage::Text(reply.to_string().into())).await?;
+                }
+            }
+            Ok(message) = push.recv() => {
+                socket.send(WsMessage::Text(message.to_string().into())).await?;
+            }
+        }
+    }
+}
+
+/// クライアントから受け取った `message` への応答
+fn replies(shared: &Shared, message: &Value, audience_token: &Option<String>) -> Vec<Value> {
+    let fixture = &shared.fixture;
+    match message["type"].as_str() {
+        Some("startWatching") => {
+            let connection = Connection {
+                audience_token: audience_token.clone(),
+                reconnect: message["data"]["reconnect"].as_bool().unwrap_or_default(),
+            };
+            shared.update_log(|log| log.connections.push(connection));
+            vec![
+                json!({ "type": "serverTime", "data": { "currentMs": "2024-01-01T00:00:00+09:00" } }),
+                json!({ "type": "seat", "data": { "keepIntervalSec": fixture.keep_interval_sec } }),
+                json!({
+                    "type": "messageServer",
+                    "data": {
+                        "viewUri": format!("{}/view", shared.base_url),
+                        "vposBaseTime": "2024-01-01T00:00:00+09:00",
+                    },
+                }),
+                json!({ "type": "statistics", "data": { "viewers": 1, "comments": 0 } }),
+            ]
+        }
+        Some("postComment") => {
+            let mut replies: Vec<Value> = fixture
+                .unrelated_error
+                .iter()
+                .map(|code| json!({ "type": "error", "data": { "code": code } }))
+                .collect();
+            let reply = match &fixture.post_error {
+                Some(code) => json!({ "type": "error", "data": { "code": code } }),
+                None => {
+                    let data = &message["data"];
+                    let anonymous = data["isAnonymous"].as_bool().unwrap_or_default();
+                    json!({
+                        "type": "postCommentResult",
+                        "data": {
+                            "chat": {
+                                "content": data["text"],
+                                "mail": if anonymous { "184" } else { "" },
+                                "anonymity": i32::from(anonymous),
+                                "restricted": false,
+                            },
+                        },
+                    })
+                }
+            };
+            replies.push(reply);
+            replies
+        }
+        _ => Vec::new(),
+    }
+}
+
+async fn view(
+    State(shared): State<Arc<Shared>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let fixture = &shared.fixture;
+    if shared.views.fetch_add(1, Ordering::SeqCst) < fixture.view_failures {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+    let begin_time = fixture.begin_time();
+    let index = match query.get("at").map_or("now", String::as_str) {
+        "now" => 0,
+        at => match at.parse::<i64>() {
+            Ok(at) => ((at - begin_time).max(0) / SEGMENT_SEC) as usize,
+            Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+        },
+    };
+
+    let mut entries = Vec::new();
+    if !fixture.history.is_empty() {
+        entries.push(Entry::Backward(BackwardSegment {
+            until: Some(timestamp(begin_time)),
+            segment: Some(Next {
+                uri: format!("{}/backward", shared.base_url),
+            }),
+            snapshot: Some(StateSnapshot {
+                uri: format!("{}/snapshot", shared.base_url),
+            }),
+        }));
+    }
+    if index < fixture.segments.len() {
+        let from = begin_time + index as i64 * SEGMENT_SEC;
+        entries.push(Entry::Segment(MessageSegment {
+            from: Some(timestamp(from)),
+            until: Some(timestamp(from + SEGMENT_SEC)),
+            uri: format!("{}/segment/{index}", shared.base_url),
+        }));
+        if index + 1 < fixture.segments.len() {
+            entries.push(Entry::Next(ReadyForNext {
+                at: from + SEGMENT_SEC,
+            }));
+        }
+    }
+
+    length_delimited(
+        entries
+            .into_iter()
+            .map(|entry| ChunkedEntry { entry: Some(entry) }),
+    )
+}
+
+async fn segment(State(shared): State<Arc<Shared>>, Path(index): Path<usize>) -> Response {
+    if let Some(body) = shared.fixture.raw.get(&format!("segment/{index}")) {
+        return protobuf_response(body.clone());
+    }
+    match shared.fixture.segments.get(index) {
+        Some(messages) => length_delimited(messages.iter().cloned()),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn snapshot(State(shared): State<Arc<Shared>>) -> Response {
+    length_delimited(shared.fixture.snapshot.iter().cloned())
+}
+
+async fn backward(State(shared): State<Arc<Shared>>) -> Response {
+    let segment = PackedSegment {
+        messages: shared.fixture.history.clone(),
+        next: None,
+        snapshot: None,
+    };
+    protobuf_response(segment.encode_to_vec())
+}
+
+async fn raw(State(shared): State<Arc<Shared>>, Path(name): Path<String>) -> Response {
+    match shared.fixture.raw.get(&name) {
+        Some(body) => protobuf_response(body.clone()),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+fn length_delimited<T: Message>(messages: impl Iterator<Item = T>) -> Response {
+    let mut body = Vec::new();
+    for message in messages {
+        message.encode_length_delimited(&mut body).unwrap();
+    }
+    protobuf_response(body)
+}
+
+fn protobuf_response(body: Vec<u8>) -> Response {
+    ([(header::CONTENT_TYPE, "application/octet-stream")], body).into_response()
+}
+
+fn timestamp(seconds: i64) -> Timestamp {
+    Timestamp { seconds, nanos: 0 }
+}
+
+/// `at` (UNIX 秒) に投稿されたコメント
+pub fn chat(id: &str, no: i32, content: &str, at: i64) -> ChunkedMessage {
+    ChunkedMessage {
+        meta: Some(meta(id, at)),
+        payload: Some(Payload::Message(NicoliveMessage {
+            data: Some(nicolive_message::Data::Chat(Chat {
+                content: content.to_string(),
+                no,
+                hashed_user_id: Some(format!("a:{id}")),
+                ..Chat::default()
+            })),
+        })),
+    }
+}
+
+/// 番組終了の状態
+pub fn program_ended(id: &str, at: i64) -> ChunkedMessage {
+    ChunkedMessage {
+        meta: Some(meta(id, at)),
+        payload: Some(Payload::State(NicoliveState {
+            program_status: Some(ProgramStatus {
+                state: protobuf::chat::data::program_status::State::Ended as i32,
+            }),
+            ..NicoliveState::default()
+        })),
+    }
+}
+
+fn meta(id: &str, at: i64) -> Meta {
+    Meta {
+        id: id.to_string(),
+        at: Some(timestamp(at)),
+        origin: None,
+    }
+}
@@ -1,3 +1,4 @@
+#[allow(clippy::large_enum_variant)]
 pub mod chat {
     pub mod data {
         pub mod atoms {
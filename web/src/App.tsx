@@ -1,5 +1,11 @@
 import { useEffect, useRef, useState } from "react";
-import { connect, type Connection, type NdgrMessage } from "./ndgr.ts";
+import {
+  connect,
+  type Connection,
+  type NdgrMessage,
+  type ProgramInfo,
+  type ProgramState,
+} from "./ndgr.ts";
 
 const MAX_COMMENTS = 1000;
 
@@ -46,6 +52,25 @@ function CommentRow({ message }: { message: NdgrMessage }) {
   );
 }
 
+/** `fetch_program_info_json` が投げる Error の `name` ごとの表示 */
+function programInfoErrorMessage(e: unknown): string {
+  const name = e instanceof Error ? e.name : "";
+  switch (name) {
+    case "programEnded":
+      return "番組は終了しています";
+    case "notStarted":
+      return "番組はまだ始まっていません";
+    case "loginRequired":
+      return "この番組の視聴にはログインが必要です";
+    case "membershipRequired":
+      return "この番組の視聴にはコミュニティ・チャンネルへの加入が必要です";
+    case "regionRestricted":
+      return "この番組はお住まいの地域では視聴できません";
+    default:
+      return `番組情報の取得に失敗しました: ${String(e)} (CORS の場合はプロキシを指定してください)`;
+  }
+}
+
 export default function App() {
   const [url, setUrl] = useState("");
   const [proxy, setProxy] = useState("");
@@ -53,6 +78,8 @@ export default function App() {
   const [status, setStatus] = useState("");
   const [isError, setIsError] = useState(false);
   const [comments, setComments] = useState<CommentEntry[]>([]);
+  const [programInfo, setProgramInfo] = useState<ProgramInfo | null>(null);
+  const [programState, setProgramState] = useState<ProgramState | null>(null);
 
   const connectionRef = useRef<Connection | null>(null);
   const listRef = useRef<HTMLDivElement>(null);
@@ -88,6 +115,8 @@ export default function App() {
 
     setConnected(true);
     setComments([]);
+    setProgramInfo(null);
+    setProgramState(null);
     stickToBottomRef.current = true;
 
     try {
@@ -98,6 +127,8 @@ export default function App() {
             return next.length > MAX_COMMENTS ? next.slice(next.length - MAX_COMMENTS) : next;
           });
         },
+        onProgram: setProgramInfo,
+        onState: setProgramState,
         onStatus: (text) => showStatus(text),
         onError: (text) => {
           showStatus(text, true);
@@ -106,10 +137,7 @@ export default function App() {
         },
       });
     } catch (e) {
-      showStatus(
-        `番組情報の取得に失敗しました: ${String(e)} (CORS の場合はプロキシを指定してください)`,
-        true,
-      );
+      showStatus(programInfoErrorMessage(e), true);
       setConnected(false);
     }
   };
@@ -161,6 +189,24 @@ export default function App() {
       <div id="status" className={isError ? "error" : ""}>
         {status}
       </div>
+      {programInfo && (
+        <div id="program-info">
+          <span className="title">{programInfo.program.title}</span>
+          <span className="supplier">
+            {programInfo.program.supplier.name}
+            {programInfo.socialGroup.name && ` (${programInfo.socialGroup.name})`}
+          </span>
+        </div>
+      )}
+      {programState && (
+        <div id="program-state">
+          来場者 {programState.statistics.viewers ?? 0} / コメント{" "}
+          {programState.statistics.comments ?? 0}
+          {programState.commentLocked && " / コメント禁止"}
+          {programState.enquete && ` / アンケート: ${programState.enquete.question}`}
+          {programState.marquee && ` / 運営: ${programState.marquee.content}`}
+        </div>
+      )}
       <div id="comments" ref={listRef} onScroll={handleScroll}>
         {comments.map((entry) => (
           <CommentRow key={entry.id} message={entry.message} />
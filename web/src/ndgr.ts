@@ -1,4 +1,4 @@
-import init, { fetch_web_socket_url, stream_comments } from "../wasm/pkg/ndgr_client_wasm.js";
+import init, { fetch_program_info_json, stream_comments } from "../wasm/pkg/ndgr_client_wasm.js";
 import wasmUrl from "../wasm/pkg/ndgr_client_wasm_bg.wasm?url";
 
 export type NdgrMessage =
@@ -22,8 +22,45 @@ export type NdgrMessage =
   | { type: "nicoad"; at: number | null; content: string }
   | { type: "notification"; at: number | null; kind: string; content: string };
 
+export interface ProgramState {
+  statistics: {
+    viewers: number | null;
+    comments: number | null;
+    adPoints: number | null;
+    giftPoints: number | null;
+  };
+  enquete: {
+    question: string;
+    choices: { description: string; perMille: number | null }[];
+    status: "poll" | "result" | "closed";
+  } | null;
+  marquee: { content: string; name: string | null; link: string | null } | null;
+  commentLocked: boolean;
+  ended: boolean;
+}
+
+export interface ProgramInfo {
+  site: { relive: { webSocketUrl: string } };
+  program: {
+    nicoliveProgramId: string;
+    title: string;
+    status: "ON_AIR" | "ENDED" | "RELEASED" | "UNKNOWN";
+    beginTime: number;
+    endTime: number;
+    supplier: { name: string };
+  };
+  socialGroup: { id: string; name: string };
+}
+
+type StreamPayload =
+  | NdgrMessage
+  | { type: "state"; state: ProgramState }
+  | { type: "programEnded" };
+
 export interface ConnectionCallbacks {
+  onProgram: (info: ProgramInfo) => void;
   onMessage: (message: NdgrMessage) => void;
+  onState: (state: ProgramState) => void;
   onStatus: (status: string) => void;
   onError: (error: string) => void;
 }
@@ -47,7 +84,9 @@ export async function connect(
   await ensureWasm();
 
   callbacks.onStatus("番組情報を取得中…");
-  const webSocketUrl: string = await fetch_web_socket_url(programUrl, proxyPrefix);
+  const info = JSON.parse(await fetch_program_info_json(programUrl, proxyPrefix)) as ProgramInfo;
+  callbacks.onProgram(info);
+  const webSocketUrl = info.site.relive.webSocketUrl;
 
   let alive = true;
   let keepSeatTimer: ReturnType<typeof setInterval> | null = null;
@@ -77,7 +116,17 @@ export async function connect(
         callbacks.onStatus("コメント受信中");
         stream_comments(viewUri, proxyPrefix, (json: string) => {
           if (!alive) return false;
-          callbacks.onMessage(JSON.parse(json) as NdgrMessage);
+          const message = JSON.parse(json) as StreamPayload;
+          if (message.type === "programEnded") {
+            callbacks.onStatus("番組が終了しました");
+            disconnect();
+            return false;
+          }
+          if (message.type === "state") {
+            callbacks.onState(message.state);
+            return true;
+          }
+          callbacks.onMessage(message);
           return true;
         }).catch((e: unknown) => {
           if (alive) {
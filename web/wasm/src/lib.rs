@@ -1,16 +1,24 @@
 use futures_util::{StreamExt, pin_mut};
-use ndgr_client::{ViewQuery, fetch_chunked_entry, fetch_chunked_message, fetch_program_info};
-use protobuf::chat::data::nicolive_message::Data;
-use protobuf::chat::data::{nicoad, simple_notification};
+use ndgr_client::error::ProgramInfoError;
+use ndgr_client::model::Message;
+use ndgr_client::state::StateTracker;
+use ndgr_client::{
+    StreamItem, StreamOptions, fetch_program_info, stream_chunked_message_with_options,
+};
 use protobuf::chat::service::edge::ChunkedMessage;
-use protobuf::chat::service::edge::chunked_entry::Entry;
-use protobuf::chat::service::edge::chunked_message::Payload;
 use wasm_bindgen::prelude::*;
 
 fn to_js_err(e: impl std::fmt::Display) -> JsValue {
     JsValue::from_str(&e.to_string())
 }
 
+/// `name` に [`ProgramInfoError::code`] を入れた JS の `Error` にする
+fn program_info_js_err(e: ProgramInfoError) -> JsValue {
+    let error = js_sys::Error::new(&e.to_string());
+    error.set_name(e.code());
+    error.into()
+}
+
 fn proxied(proxy_prefix: &str, url: &str) -> String {
     format!("{proxy_prefix}{url}")
 }
@@ -29,124 +37,73 @@ pub async fn fetch_web_socket_url(
 ) -> Result<String, JsValue> {
     let info = fetch_program_info(&proxied(&proxy_prefix, &page_url))
         .await
-        .map_err(to_js_err)?;
+        .map_err(program_info_js_err)?;
     Ok(info.site.relive.web_socket_url)
 }
 
+/// 番組ページの HTML から番組情報 (タイトル・放送者・状態・WebSocket URL など) を取り出し、
+/// JSON 文字列で返す。
+/// 視聴できない場合は `name` に理由 (`programEnded` や `loginRequired` など) を入れた
+/// `Error` を投げる。
+#[wasm_bindgen]
+pub async fn fetch_program_info_json(
+    page_url: String,
+    proxy_prefix: String,
+) -> Result<String, JsValue> {
+    let info = fetch_program_info(&proxied(&proxy_prefix, &page_url))
+        .await
+        .map_err(program_info_js_err)?;
+    serde_json::to_string(&info).map_err(to_js_err)
+}
+
 /// NDGR メッセージサーバーからコメントをストリーミングし、
 /// 1 件ごとに JSON 文字列で `on_message` を呼ぶ。
 /// コールバックが `false` を返したら停止する。
+/// 番組の状態 (来場者数・アンケートなど) が変わったら `{"type":"state","state":{…}}` を渡す。
+/// 番組が終了したら `{"type":"programEnded"}` を渡して正常終了する。
 #[wasm_bindgen]
 pub async fn stream_comments(
     view_uri: String,
     proxy_prefix: String,
     on_message: js_sys::Function,
 ) -> Result<(), JsValue> {
-    let mut view_query = ViewQuery::Now;
-
-    loop {
-        let mut got_next = false;
-
-        let stream = fetch_chunked_entry(&proxied(&proxy_prefix, &view_uri), &view_query).await;
-        pin_mut!(stream);
+    let options = StreamOptions {
+        uri_prefix: proxy_prefix,
+        ..Default::default()
+    };
+    let stream = stream_chunked_message_with_options(&view_uri, options).await;
+    pin_mut!(stream);
 
-        while let Some(entry) = stream.next().await {
-            let entry = entry.map_err(to_js_err)?;
-            match entry.entry {
-                Some(Entry::Next(next)) => {
-                    view_query = ViewQuery::At(next.at);
-                    got_next = true;
-                }
-                Some(Entry::Segment(segment)) => {
-                    let messages =
-                        fetch_chunked_message(&proxied(&proxy_prefix, &segment.uri)).await;
-                    pin_mut!(messages);
+    let mut state_tracker = StateTracker::new();
 
-                    while let Some(message) = messages.next().await {
-                        let message = message.map_err(to_js_err)?;
-                        if let Some(json) = chunked_message_to_json(&message) {
-                            let keep_going = on_message
-                                .call1(&JsValue::NULL, &JsValue::from_str(&json.to_string()))?;
-                            if keep_going.is_falsy() {
-                                return Ok(());
-                            }
-                        }
-                    }
-                }
-                _ => (),
+    while let Some(item) = stream.next().await {
+        let json = match item {
+            Ok(StreamItem::Message(message)) if state_tracker.apply_message(&message) => {
+                serde_json::json!({ "type": "state", "state": state_tracker.state() })
             }
-        }
+            Ok(StreamItem::Message(message)) => match chunked_message_to_json(&message) {
+                Some(json) => json,
+                None => continue,
+            },
+            Ok(StreamItem::ProgramEnded) => serde_json::json!({ "type": "programEnded" }),
+            // 再試行中のエラーはストリーム側で待機してやり直すので流さない
+            Err(e) if e.retrying => continue,
+            Err(e) => return Err(to_js_err(e)),
+        };
 
-        if !got_next {
-            return Err(JsValue::from_str(
-                "stream ended (program may have finished)",
-            ));
+        let keep_going = on_message.call1(&JsValue::NULL, &JsValue::from_str(&json.to_string()))?;
+        if keep_going.is_falsy() {
+            return Ok(());
         }
     }
+
+    Ok(())
 }
 
 fn chunked_message_to_json(message: &ChunkedMessage) -> Option<serde_json::Value> {
-    let at = message
-        .meta
-        .as_ref()
-        .and_then(|meta| meta.at.as_ref())
-        .map(|at| at.seconds);
-
-    let Some(Payload::Message(message)) = &message.payload else {
+    let message = Message::from_chunked_message(message)?;
+    if !message.event.is_comment() {
         return None;
-    };
-
-    let mut json = match message.data.as_ref()? {
-        Data::Chat(chat) | Data::OverflowedChat(chat) => serde_json::json!({
-            "type": "chat",
-            "content": chat.content,
-            "name": chat.name,
-            "rawUserId": chat.raw_user_id,
-            "hashedUserId": chat.hashed_user_id,
-            "premium": chat.account_status == 1,
-        }),
-        Data::SimpleNotification(notification) => {
-            use simple_notification::Message;
-            let (kind, content) = match notification.message.as_ref()? {
-                Message::Ichiba(s) => ("ichiba", s),
-                Message::Quote(s) => ("quote", s),
-                Message::Emotion(s) => ("emotion", s),
-                Message::Cruise(s) => ("cruise", s),
-                Message::ProgramExtended(s) => ("programExtended", s),
-                Message::RankingIn(s) => ("rankingIn", s),
-                Message::RankingUpdated(s) => ("rankingUpdated", s),
-                Message::Visited(s) => ("visited", s),
-            };
-            serde_json::json!({
-                "type": "notification",
-                "kind": kind,
-                "content": content,
-            })
-        }
-        Data::Gift(gift) => serde_json::json!({
-            "type": "gift",
-            "advertiserName": gift.advertiser_name,
-            "itemName": gift.item_name,
-            "point": gift.point,
-            "message": gift.message,
-        }),
-        Data::Nicoad(ad) => {
-            let content = match ad.versions.as_ref()? {
-                nicoad::Versions::V0(v0) => v0
-                    .latest
-                    .as_ref()
-                    .map(|latest| format!("{} {}pt", latest.advertiser, latest.point))
-                    .unwrap_or_default(),
-                nicoad::Versions::V1(v1) => v1.message.clone(),
-            };
-            serde_json::json!({
-                "type": "nicoad",
-                "content": content,
-            })
-        }
-        _ => return None,
-    };
-
-    json["at"] = serde_json::json!(at);
-    Some(json)
+    }
+    serde_json::to_value(message).ok()
 }